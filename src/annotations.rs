@@ -0,0 +1,108 @@
+//! Operator notes per ASN, loaded from a local JSON file mapping ASN to note string:
+//!
+//! ```json
+//! { "13335": "CDN, contact via PeeringDB", "64512": "lab ASN" }
+//! ```
+
+use crate::api::{dataset_etag, AppState, AsInfoOut};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+pub type Annotations = HashMap<u32, String>;
+
+/// How often the annotations file is checked for changes while serving.
+const ANNOTATIONS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+pub fn load_annotations(path: &str) -> Result<Annotations, i32> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        error!("failed to read annotations file '{path}': {e}");
+        1
+    })?;
+    let annotations: Annotations = serde_json::from_str(&content).map_err(|e| {
+        error!("failed to parse annotations file '{path}': {e}");
+        1
+    })?;
+    info!("loaded {} annotations from '{}'", annotations.len(), path);
+    Ok(annotations)
+}
+
+/// Set or clear the `note` of every record according to `annotations`.
+pub fn apply_annotations(map: &mut HashMap<u32, AsInfoOut>, annotations: &Annotations) {
+    for (asn, info) in map.iter_mut() {
+        info.note = annotations.get(asn).cloned();
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Apply new notes to the served records and swap them in. The records are annotated on a
+/// snapshot outside the `map` lock, which is only held for the swap. Returns false if annotating
+/// failed, in which case the previous notes stay in place.
+pub async fn reload_annotations(state: &AppState, new_annotations: Annotations) -> bool {
+    let _writer = state.records_writer.lock().await;
+    // annotate a snapshot off-lock; with --compress-records this recompresses every record
+    let mut records = state
+        .map
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    let annotated = tokio::task::spawn_blocking(move || {
+        let etag = records.update(|map| {
+            apply_annotations(map, &new_annotations);
+            dataset_etag(map)
+        });
+        (records, etag, new_annotations)
+    })
+    .await;
+    let (records, etag, new_annotations) = match annotated {
+        Ok(annotated) => annotated,
+        Err(e) => {
+            error!("annotations watcher: annotate task failed: {e}");
+            return false;
+        }
+    };
+    let mut map_guard = state.map.lock().unwrap_or_else(|poisoned| {
+        error!("annotations watcher: map mutex is poisoned, recovering");
+        poisoned.into_inner()
+    });
+    let mut annotations_guard = state.annotations.lock().unwrap_or_else(|poisoned| {
+        error!("annotations watcher: annotations mutex is poisoned, recovering");
+        poisoned.into_inner()
+    });
+    let mut etag_guard = state.etag.lock().unwrap_or_else(|poisoned| {
+        error!("annotations watcher: etag mutex is poisoned, recovering");
+        poisoned.into_inner()
+    });
+    *map_guard = records;
+    *etag_guard = etag;
+    *annotations_guard = new_annotations;
+    true
+}
+
+/// Reload the annotations file whenever its modification time changes and re-apply the notes to
+/// the served records.
+pub fn start_annotations_watcher(path: String, state: AppState) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut last_modified = modified_at(&path);
+        loop {
+            sleep(ANNOTATIONS_POLL_INTERVAL).await;
+            let modified = modified_at(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            // keep serving the previous notes if the new file is broken
+            let Ok(new_annotations) = load_annotations(&path) else {
+                continue;
+            };
+            if reload_annotations(&state, new_annotations).await {
+                info!("annotations watcher: notes reloaded");
+            }
+        }
+    })
+}
@@ -1,297 +1,2659 @@
+use crate::annotations::{apply_annotations, Annotations};
+use crate::cache;
+use crate::dataset::{
+    load_commons, load_commons_partial, load_concurrently, load_snapshot, DatasetManifest,
+    LoadRetry,
+};
+use crate::metrics::Metrics;
+use crate::peeringdb::{ix_list, load_ix_presence};
+use crate::pfx2as::Pfx2As;
+use crate::ratelimit::RateLimiter;
+use crate::store::RecordMap;
+use crate::upstream::UpstreamProbe;
 use axum::{
-    extract::{Query, Request as AxumRequest, State},
-    http::{Method, StatusCode},
+    body::{Body, Bytes},
+    extract::{ConnectInfo, MatchedPath, Path, Query, RawQuery, Request as AxumRequest, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     middleware::{self, Next},
-    response::Response,
-    routing::get,
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
-use bgpkit_commons::asinfo::AsInfo;
+use bgpkit_commons::asinfo::{As2orgInfo, AsInfo};
+use bgpkit_commons::BgpkitCommons;
 use chrono::{SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::{Notify, Semaphore};
 use tokio::task::JoinHandle;
-use tokio::time::sleep;
+use tokio::time::{sleep_until, Instant};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info};
+use tracing::{error, info, warn, Instrument};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Number of records serialized per chunk when streaming `/dump`.
+const DUMP_CHUNK_RECORDS: usize = 256;
+
+/// Maximum number of ASNs covered by a `/range` query.
+const MAX_RANGE_SPAN: u32 = 100_000;
+
+/// Response header carrying the number of records in the currently loaded dataset.
+const DATASET_TOTAL_HEADER: &str = "x-dataset-total";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Request and response header carrying the ID of a request, see [`log_requests`].
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request ID that is propagated; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Page size used when a paginated request does not set `page_size`.
+const DEFAULT_PAGE_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AsInfoOut {
+    /// Upstream record, flattened; `as2org`, `population`, `hegemony`, and `peeringdb` serialize
+    /// as null when the ASN has no data in that dataset or the dataset is not loaded
     #[serde(flatten)]
+    #[schema(value_type = AsInfoSchema)]
     pub inner: AsInfo,
-    #[serde(rename = "country_name")]
-    pub country_name: String,
+    /// Country name for `country`; `None` if the code is unknown or with `--compact-country`
+    #[serde(
+        rename = "country_name",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub country_name: Option<String>,
+    /// Continent code of `country` (e.g. `NA`, `EU`); only set with `--with-continent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continent: Option<String>,
+    /// All country codes observed for this ASN across datasets; only set with `--with-geo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub countries: Option<Vec<String>>,
+    /// Number of IXes this ASN is present at; only set with `--with-ix`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ix_count: Option<usize>,
+    /// Names of the IXes this ASN is present at; only set with `--with-ix`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ixs: Option<Vec<String>>,
+    /// Operator note from the annotations file, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Datasets that contributed to this record, see [`data_source`]
+    #[serde(default)]
+    pub data_source: String,
+}
+
+/// OpenAPI description of the upstream `AsInfo` fields flattened into [`AsInfoOut`]; only used
+/// for `/openapi.json`.
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct AsInfoSchema {
+    asn: u32,
+    /// AS name from RIPE NCC
+    name: String,
+    /// Two-letter country code of the AS registration
+    country: String,
+    /// CAIDA as2org record with `name`, `country`, `org_id`, and `org_name`
+    #[schema(value_type = Option<Object>)]
+    as2org: Option<Value>,
+    /// APNIC user population estimate; null when the ASN has no estimate and in simplified mode
+    population: Option<PopulationSchema>,
+    /// IIJ IHR AS hegemony scores; absent in simplified mode
+    #[schema(value_type = Option<Object>)]
+    hegemony: Option<Value>,
+    /// PeeringDB network record; absent in simplified mode
+    #[schema(value_type = Option<Object>)]
+    peeringdb: Option<Value>,
+}
+
+/// OpenAPI description of the APNIC population estimate in [`AsInfoSchema`].
+#[derive(ToSchema)]
+#[allow(dead_code)]
+struct PopulationSchema {
+    /// Estimated number of users behind the ASN
+    user_count: i64,
+    /// Number of ad impressions the estimate is based on
+    sample_count: i64,
+    /// Share of the registration country's users, in percent
+    percent_country: f64,
+    /// Share of all users worldwide, in percent
+    percent_global: f64,
+}
+
+/// Options controlling which datasets are loaded and how records are enriched.
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Skip heavy datasets (population, hegemony, PeeringDB)
+    pub simplified: bool,
+    /// Populate `countries` with every country observed for an ASN
+    pub with_geo: bool,
+    /// Populate `ix_count`/`ixs` from PeeringDB netixlan data
+    pub with_ix: bool,
+    /// Omit `country_name` and skip loading country data
+    pub compact_country: bool,
+    /// Populate `continent` from the country record
+    pub with_continent: bool,
+    /// Which dataset sources to load
+    pub manifest: DatasetManifest,
+    /// Keep served records zstd-compressed in memory
+    pub compress_records: bool,
+    /// Load records from this dump file instead of the upstream sources
+    pub from_file: Option<String>,
+    /// Retries of a failed upstream dataset load
+    pub retry: LoadRetry,
+    /// Directory to persist complete loads to, for fast restarts (see [`crate::cache`])
+    pub cache_dir: Option<String>,
+}
+
+/// Whether a dataset was loaded and how many records it contributed to.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatasetStatus {
+    pub name: &'static str,
+    pub loaded: bool,
+    /// Requested but given up after its load failed; the updater retries early
+    pub failed: bool,
+    pub records: usize,
 }
 
+/// Records keyed by ASN, their load timestamp, and the requested datasets that failed to load.
+pub type LoadedRecords = (HashMap<u32, AsInfoOut>, String, Vec<&'static str>);
+
+/// Org key (lowercased org name, or org ID) to the sorted ASNs registered under it.
+pub type OrgIndex = HashMap<String, Vec<u32>>;
+
 #[derive(Clone)]
 pub struct AppState {
-    pub map: Arc<Mutex<HashMap<u32, AsInfoOut>>>,
+    pub map: Arc<Mutex<RecordMap>>,
+    pub org_index: Arc<Mutex<OrgIndex>>,
+    /// as2org org ID to its ASNs, for `/org/{org_id}`
+    pub org_id_index: Arc<Mutex<OrgIndex>>,
+    pub annotations: Arc<Mutex<Annotations>>,
+    /// Strong ETag of the current dataset, recomputed whenever the served records change
+    pub etag: Arc<Mutex<String>>,
+    /// Load status of each dataset, recomputed on refresh
+    pub datasets: Arc<Mutex<Vec<DatasetStatus>>>,
+    /// Records from the previous snapshot that differ from the current one, for `history=true`
+    pub previous: Arc<Mutex<HashMap<u32, AsInfoOut>>>,
     pub updated_at: Arc<Mutex<String>>,
     pub max_asns: usize,
+    /// Hard cap a lookup can raise its ASN limit to with `limit`; at least `max_asns`
+    pub max_asns_ceiling: usize,
+    /// Whether records carry IX presence data, required for `at_ix=true`
+    pub with_ix: bool,
+    /// Caps how many large batches are rendered concurrently on the blocking pool
+    pub batch_permits: Arc<Semaphore>,
+    /// Set when `--entry-ttl-secs` is configured; lookups use it to ask the updater for an early
+    /// refresh of stale data
+    pub refresh_hint: Option<Arc<Notify>>,
+    /// Prefix-to-origin data for IP-to-ASN resolution; only set with `--with-pfx2as`
+    pub pfx2as: Option<Arc<Mutex<Pfx2As>>>,
+    /// Upstream reachability probe for `/health/upstream`; only set with `--probe-upstream`
+    pub upstream_probe: Option<Arc<UpstreamProbe>>,
+    /// Request and dataset metrics exposed on `/metrics`
+    pub metrics: Arc<Metrics>,
+    /// Earliest time the updater may replace the dataset, used for `Cache-Control: max-age`
+    pub next_refresh: Arc<Mutex<Option<Instant>>>,
+    /// Bearer token required on every endpoint except `/health`; the API is open when unset
+    pub api_token: Option<Arc<str>>,
+    /// Per-client request limit; only set with `ASNINFO_RATE_LIMIT`
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Outcome of the latest data load, recorded by the updater for `/health/ready`
+    pub refresh_status: Arc<Mutex<RefreshStatus>>,
+    /// Held by the updater and the annotations watcher while they rebuild the records outside the
+    /// `map` lock, so neither swaps in a copy built from records the other has since replaced
+    pub records_writer: Arc<tokio::sync::Mutex<()>>,
 }
 
-#[derive(Deserialize)]
+/// Outcome of the latest data load; set up by [`start_updater`] and updated on every refresh.
+#[derive(Debug, Clone, Default)]
+pub struct RefreshStatus {
+    /// When the served data was last loaded successfully
+    pub last_success: Option<Instant>,
+    /// Whether the most recent refresh attempt failed
+    pub last_failed: bool,
+    /// Served data older than this is stale: twice the refresh interval
+    pub stale_after: Option<Duration>,
+}
+
+impl AppState {
+    /// ASN limit of a lookup: `max_asns`, or the requested `limit` capped at `max_asns_ceiling`.
+    fn asn_limit(&self, requested: Option<usize>) -> usize {
+        requested.map_or(self.max_asns, |limit| limit.min(self.max_asns_ceiling))
+    }
+
+    /// Signal the updater that data was queried. The updater only refreshes if the data is older
+    /// than the entry TTL, so this is cheap and never blocks the request.
+    fn hint_refresh(&self) {
+        if let Some(hint) = &self.refresh_hint {
+            hint.notify_one();
+        }
+    }
+
+    /// `Cache-Control` value letting clients cache responses until the next possible refresh.
+    fn cache_control(&self) -> Option<HeaderValue> {
+        let next = (*self.next_refresh.lock().unwrap_or_else(|e| e.into_inner()))?;
+        let remaining = next.saturating_duration_since(Instant::now()).as_secs();
+        HeaderValue::from_str(&format!("max-age={remaining}")).ok()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct LookupQuery {
+    /// Comma-separated ASNs, optionally prefixed with `AS` or `ASN`
     asns: Option<String>,
+    /// Return the legacy flat record format
     legacy: Option<bool>,
+    /// Only return ASNs present at one or more IXes (requires `--with-ix`)
+    at_ix: Option<bool>,
+    /// Add the record as it was before the last refresh under `previous`
+    history: Option<bool>,
+    /// Return `{found, missing}` listing requested ASNs without a record
+    include_missing: Option<bool>,
+    /// Skip invalid ASNs instead of rejecting the request
+    lenient: Option<bool>,
+    /// Page number, starting at 1; enables the paginated envelope
+    page: Option<usize>,
+    /// Records per page, capped at `ASNINFO_MAX_ASNS`; enables the paginated envelope
+    page_size: Option<usize>,
+    /// Maximum ASNs for this request instead of `ASNINFO_MAX_ASNS`, capped at
+    /// `ASNINFO_MAX_ASNS_CEILING`
+    limit: Option<usize>,
+    /// Comma-separated record fields to return, e.g. `name,country`; `asn` is always included
+    fields: Option<String>,
+}
+
+/// Top-level keys of a serialized [`AsInfoOut`] a lookup can be projected down to with `fields`;
+/// `asn` is always kept.
+const PROJECTABLE_FIELDS: &[&str] = &[
+    "asn",
+    "name",
+    "country",
+    "as2org",
+    "population",
+    "hegemony",
+    "peeringdb",
+    "country_name",
+    "continent",
+    "countries",
+    "ix_count",
+    "ixs",
+    "note",
+    "data_source",
+];
+
+/// Parse a comma-separated `fields` list, rejecting names not in [`PROJECTABLE_FIELDS`].
+fn parse_fields(fields: Option<&str>) -> Result<Option<Vec<String>>, (StatusCode, Json<Value>)> {
+    let Some(fields) = fields else {
+        return Ok(None);
+    };
+    let fields: Vec<String> = fields
+        .split(',')
+        .map(|f| f.trim().to_lowercase())
+        .filter(|f| !f.is_empty())
+        .collect();
+    let unknown: Vec<&String> = fields
+        .iter()
+        .filter(|f| !PROJECTABLE_FIELDS.contains(&f.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": format!("unknown fields, choose from {}", PROJECTABLE_FIELDS.join(", ")),
+                "invalid": unknown,
+            })),
+        ));
+    }
+    Ok((!fields.is_empty()).then_some(fields))
+}
+
+/// Reduce each rendered record to `asn` and `fields`; a field the record lacks is set to null
+/// rather than omitted, so e.g. ASNs without a population estimate still carry `population`.
+///
+/// Projection works on the serialized records, so it cannot drift from the `AsInfoOut` serde
+/// attributes.
+fn project_fields(results: &mut Value, fields: &[String]) {
+    let Some(records) = results.as_array_mut() else {
+        return;
+    };
+    for record in records.iter_mut() {
+        let Some(obj) = record.as_object() else {
+            continue;
+        };
+        let mut projected = serde_json::Map::new();
+        projected.insert(
+            "asn".to_string(),
+            obj.get("asn").cloned().unwrap_or_default(),
+        );
+        for field in fields {
+            projected.insert(field.clone(), obj.get(field).cloned().unwrap_or_default());
+        }
+        *record = Value::Object(projected);
+    }
+}
+
+/// Envelope of a paginated response; `count` is the number of results across all pages.
+#[derive(Serialize, ToSchema)]
+struct LookupResponse<T> {
+    count: usize,
+    #[serde(rename = "updatedAt")]
+    updated_at: String,
+    page: usize,
+    page_size: usize,
+    data: T,
+}
+
+/// A 1-based page of results with a page size capped at `max_page_size`.
+#[derive(Debug, Clone, Copy)]
+struct Page {
+    page: usize,
+    page_size: usize,
+}
+
+impl Page {
+    fn new(page: Option<usize>, page_size: Option<usize>, max_page_size: usize) -> Self {
+        Page {
+            page: page.unwrap_or(1).max(1),
+            page_size: page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, max_page_size.max(1)),
+        }
+    }
+
+    /// The items of this page; empty when the page is past the end.
+    fn slice<T>(&self, items: Vec<T>) -> Vec<T> {
+        let start = (self.page - 1).saturating_mul(self.page_size);
+        items.into_iter().skip(start).take(self.page_size).collect()
+    }
+
+    fn response<T>(&self, count: usize, updated_at: String, data: T) -> LookupResponse<T> {
+        LookupResponse {
+            count,
+            updated_at,
+            page: self.page,
+            page_size: self.page_size,
+            data,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    /// Case-insensitive substring of the AS name or as2org org name
+    q: Option<String>,
+    /// Maximum number of results, capped at `ASNINFO_MAX_ASNS`
+    limit: Option<usize>,
+    /// Only return records registered in this two-letter country code
+    country: Option<String>,
+}
+
+/// Body of `POST /search`; every filter present must match.
+#[derive(Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+struct SearchBody {
+    /// Case-insensitive substring of the AS name
+    name_contains: Option<String>,
+    /// Case-insensitive substring of the as2org org name
+    org_contains: Option<String>,
+    /// Two-letter country code of the AS registration
+    country: Option<String>,
+    /// Maximum number of results, capped at `ASNINFO_MAX_ASNS`
+    limit: Option<usize>,
+}
+
+/// Record filters shared by `GET /search` and `POST /search`, combined with AND semantics.
+/// Terms are lowercased and the country uppercased by [`SearchFilter::new`].
+#[derive(Default)]
+struct SearchFilter {
+    /// Substring of the AS name or the org name (`q`)
+    name_or_org: Option<String>,
+    name: Option<String>,
+    org: Option<String>,
+    country: Option<String>,
+}
+
+impl SearchFilter {
+    fn new(
+        name_or_org: Option<String>,
+        name: Option<String>,
+        org: Option<String>,
+        country: Option<String>,
+    ) -> Self {
+        let term = |s: Option<String>| s.map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty());
+        SearchFilter {
+            name_or_org: term(name_or_org),
+            name: term(name),
+            org: term(org),
+            country: term(country).map(|c| c.to_uppercase()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.name_or_org.is_none()
+            && self.name.is_none()
+            && self.org.is_none()
+            && self.country.is_none()
+    }
+
+    fn matches(&self, info: &AsInfoOut) -> bool {
+        let name_matches = |term: &str| info.inner.name.to_lowercase().contains(term);
+        let org_matches = |term: &str| {
+            info.inner
+                .as2org
+                .as_ref()
+                .is_some_and(|v| v.org_name.to_lowercase().contains(term))
+        };
+        self.country
+            .as_ref()
+            .is_none_or(|c| info.inner.country.eq_ignore_ascii_case(c))
+            && self.name.as_deref().is_none_or(name_matches)
+            && self.org.as_deref().is_none_or(org_matches)
+            && self
+                .name_or_org
+                .as_deref()
+                .is_none_or(|term| name_matches(term) || org_matches(term))
+    }
+}
+
+#[derive(Deserialize)]
+struct IpQuery {
+    addr: Option<String>,
 }
 
 #[derive(Deserialize)]
+struct RangeQuery {
+    start: Option<u32>,
+    end: Option<u32>,
+    page: Option<usize>,
+    page_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CountryQuery {
+    code: Option<String>,
+    page: Option<usize>,
+    page_size: Option<usize>,
+}
+
+#[derive(Deserialize, ToSchema)]
 struct LookupBody {
     asns: Vec<u32>,
+    /// Maximum ASNs for this request instead of `ASNINFO_MAX_ASNS`, capped at
+    /// `ASNINFO_MAX_ASNS_CEILING`
+    #[serde(default)]
+    limit: Option<usize>,
+    /// Comma-separated record fields to return as for `GET /lookup`; `asn` is always included
+    #[serde(default)]
+    fields: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OrgLookupBody {
+    orgs: Vec<String>,
 }
 
+/// Response bodies smaller than this are not compressed; streamed bodies of unknown size are.
+const MIN_COMPRESSED_BODY_BYTES: u16 = 1024;
+
 pub fn build_router(state: AppState) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods([Method::GET, Method::POST])
-        .allow_headers(Any);
+        .allow_headers(Any)
+        .expose_headers([
+            HeaderName::from_static(DATASET_TOTAL_HEADER),
+            HeaderName::from_static(REQUEST_ID_HEADER),
+            http::header::ETAG,
+        ]);
 
     Router::new()
         .route("/lookup", get(get_lookup).post(post_lookup))
+        .route("/lookup/stream", post(post_lookup_stream))
+        .route("/lookup/ip", get(get_lookup_ip))
+        .route("/search", get(search).post(post_search))
+        .route("/org/lookup", post(post_org_lookup))
+        .route("/org/{org_id}", get(get_org))
+        .route("/by-country", get(by_country))
+        .route("/range", get(range))
+        .route("/domain/{name}", get(get_domain))
+        .route("/dump", get(dump))
+        .route("/datasets", get(datasets))
         .route("/health", get(health))
+        .route("/health/upstream", get(health_upstream))
+        .route("/health/live", get(health_live))
+        .route("/health/ready", get(health_ready))
+        .route("/metrics", get(metrics))
+        .route("/openapi.json", get(openapi))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            count_requests,
+        ))
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
         .with_state(state)
-        // log all requests except /health
+        // gzip/br per Accept-Encoding; small bodies such as /health are sent as-is
+        .layer(
+            CompressionLayer::new().compress_when(
+                DefaultPredicate::new().and(SizeAbove::new(MIN_COMPRESSED_BODY_BYTES)),
+            ),
+        )
+        // log all requests except the /health endpoints and /metrics
         .layer(middleware::from_fn(log_requests))
         .layer(cors)
 }
 
-// Middleware to log requests, skipping /health
+/// OpenAPI document for the lookup, search, and health endpoints, served at `/openapi.json`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "asninfo API"),
+    paths(
+        get_lookup,
+        post_lookup,
+        search,
+        post_search,
+        health,
+        health_live,
+        health_ready
+    ),
+    components(schemas(AsInfoOut, LookupResponse<Vec<AsInfoOut>>, SearchBody))
+)]
+struct ApiDoc;
+
+async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// Middleware to count requests per matched route for /metrics
+async fn count_requests(State(state): State<AppState>, req: AxumRequest, next: Next) -> Response {
+    if let Some(path) = req.extensions().get::<MatchedPath>() {
+        state.metrics.record_request(path.as_str());
+    }
+    next.run(req).await
+}
+
+// Middleware to require `Authorization: Bearer <token>` when an API token is configured,
+// exempting the /health endpoints
+async fn require_token(State(state): State<AppState>, req: AxumRequest, next: Next) -> Response {
+    let Some(token) = &state.api_token else {
+        return next.run(req).await;
+    };
+    if is_health_path(req.uri().path()) {
+        return next.run(req).await;
+    }
+    let provided = req
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if provided.is_some_and(|p| tokens_match(p.as_bytes(), token.as_bytes())) {
+        return next.run(req).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        [(http::header::WWW_AUTHENTICATE, "Bearer")],
+        Json(json!({"error": "missing or invalid bearer token"})),
+    )
+        .into_response()
+}
+
+/// Compare tokens without short-circuiting on the first differing byte.
+fn tokens_match(provided: &[u8], expected: &[u8]) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .iter()
+            .zip(expected)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Whether `path` is `/health` or one of its subpaths, which are exempt from authentication, rate
+/// limiting, and request logging.
+fn is_health_path(path: &str) -> bool {
+    path == "/health" || path.starts_with("/health/")
+}
+
+// Middleware to limit requests per client IP, exempting the /health endpoints
+async fn rate_limit(State(state): State<AppState>, req: AxumRequest, next: Next) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(req).await;
+    };
+    let path = req.uri().path();
+    if is_health_path(path) {
+        return next.run(req).await;
+    }
+    let Some(client) = client_ip(&req) else {
+        return next.run(req).await;
+    };
+    match limiter.check(client) {
+        Ok(()) => next.run(req).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(http::header::RETRY_AFTER, retry_after.to_string())],
+                Json(json!({"error": "rate limit exceeded"})),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Client address of a request. `X-Forwarded-For` is only honored when the peer is a local proxy
+/// (loopback, private IPv4, or a unix socket), so direct clients cannot pick their own bucket.
+/// Only the rightmost entry is used: it is the one appended by that proxy, while anything to its
+/// left was supplied by the client.
+fn client_ip(req: &AxumRequest) -> Option<IpAddr> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+    let trusted_proxy = match peer {
+        None => true,
+        Some(IpAddr::V4(ip)) => ip.is_loopback() || ip.is_private(),
+        Some(IpAddr::V6(ip)) => ip.is_loopback(),
+    };
+    if trusted_proxy {
+        let forwarded = req
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|v| v.trim().parse::<IpAddr>().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    peer
+}
+
+// Middleware to log requests, skipping the /health endpoints and /metrics.
+//
+// Each logged request runs in a span carrying its ID, so everything it logs can be correlated
+// across a load balancer. A client-supplied `X-Request-Id` is propagated, otherwise a UUID is
+// generated; either way the ID is echoed in the `X-Request-Id` response header.
 async fn log_requests(req: AxumRequest, next: Next) -> Response {
     let path = req.uri().path().to_string();
-    if path == "/health" {
+    if is_health_path(&path) || path == "/metrics" {
         return next.run(req).await;
     }
-    let method = req.method().clone();
-    let start = std::time::Instant::now();
-    let response = next.run(req).await;
-    let status = response.status();
-    let elapsed_ms = start.elapsed().as_millis();
-    info!(
-        method = %method,
-        path = %path,
-        status = %status.as_u16(),
-        latency_ms = elapsed_ms,
-        "request"
-    );
-    response
-}
-
-pub fn load_asn_map_out(simplified: bool) -> Result<(HashMap<u32, AsInfoOut>, String), i32> {
-    let load_population = !simplified;
-    let load_hegemony = !simplified;
-    let load_peeringdb = !simplified;
-
-    info!("loading asn info data ...");
-    let mut commons = bgpkit_commons::BgpkitCommons::new();
-    if let Err(e) = commons.load_asinfo(true, load_population, load_hegemony, load_peeringdb) {
-        error!("failed to load asn info data: {e}");
-        return Err(1);
-    };
-    if let Err(e) = commons.load_countries() {
-        error!("failed to load countries: {e}");
-        return Err(2);
-    };
-    let as_info_map = match commons.asinfo_all() {
-        Ok(map) => map,
-        Err(e) => {
-            error!("failed to get asinfo map: {e}");
-            return Err(3);
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = tracing::info_span!("request", request_id = %request_id);
+    async move {
+        let method = req.method().clone();
+        let start = std::time::Instant::now();
+        let mut response = next.run(req).await;
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        let status = response.status();
+        let elapsed_ms = start.elapsed().as_millis();
+        info!(
+            method = %method,
+            path = %path,
+            status = %status.as_u16(),
+            latency_ms = elapsed_ms,
+            "request"
+        );
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+/// Load and enrich all records. With `allow_partial`, if an optional upstream dataset fails, the
+/// records are loaded without it rather than failing, and the dataset is reported as failed.
+///
+/// Only startup allows partial loads; a refresh that cannot load every dataset fails instead, so
+/// the updater keeps serving the previous, complete data.
+pub fn load_asn_map_out(opts: &LoadOptions, allow_partial: bool) -> Result<LoadedRecords, i32> {
+    let mut manifest = opts.manifest.clone();
+    if opts.compact_country || opts.from_file.is_some() {
+        manifest.countries.enabled = false;
+    }
+    // the netixlan download does not depend on bgpkit-commons, so it runs alongside it
+    let load_records = || -> Result<_, i32> {
+        match &opts.from_file {
+            // offline: nothing is loaded into commons, so country lookups come back empty
+            Some(file) => Ok((BgpkitCommons::new(), load_snapshot(file)?, Vec::new())),
+            None => {
+                let ((commons, as_info_map), failed) = match allow_partial {
+                    true => load_commons_partial(&manifest, opts.simplified, &opts.retry)?,
+                    false => (
+                        load_commons(&manifest, opts.simplified, &opts.retry)?,
+                        Vec::new(),
+                    ),
+                };
+                Ok((commons, as_info_map, failed))
+            }
         }
     };
+    let ((commons, as_info_map, failed), ix_presence) =
+        load_concurrently(load_records, opts.with_ix.then_some(load_ix_presence))?;
 
     // build enriched map with country_name
     let mut out: HashMap<u32, AsInfoOut> = HashMap::with_capacity(as_info_map.len());
     for (asn, info) in as_info_map.iter() {
-        let country_name = commons
-            .country_by_code(&info.country)
-            .ok()
-            .flatten()
-            .map(|c| c.name)
-            .unwrap_or_default();
+        let country = commons.country_by_code(&info.country).ok().flatten();
+        let continent = match (&country, opts.with_continent) {
+            (Some(c), true) => Some(c.continent.clone()),
+            _ => None,
+        };
+        let country_name = match opts.compact_country {
+            true => None,
+            false => country.map(|c| c.name),
+        };
+        let ixs = ix_list(ix_presence.as_ref(), *asn);
         out.insert(
             *asn,
             AsInfoOut {
                 inner: info.clone(),
                 country_name,
+                continent,
+                countries: opts.with_geo.then(|| observed_countries(info)),
+                ix_count: ixs.as_ref().map(|v| v.len()),
+                ixs,
+                note: None,
+                data_source: data_source(info),
             },
         );
     }
     let updated_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    // only cache complete upstream loads, so a restart never pins a partial dataset
+    if let (Some(dir), None, true) = (&opts.cache_dir, &opts.from_file, failed.is_empty()) {
+        cache::store(dir, opts, &out, &updated_at);
+    }
+
+    Ok((out, updated_at, failed))
+}
+
+/// Distinct, non-empty country codes observed for an ASN, primary country first.
+///
+/// bgpkit-commons has no per-prefix geolocation, so this covers the registration country and the
+/// as2org org country.
+fn observed_countries(info: &AsInfo) -> Vec<String> {
+    let mut countries: Vec<String> = Vec::new();
+    let candidates = std::iter::once(info.country.as_str())
+        .chain(info.as2org.as_ref().map(|v| v.country.as_str()));
+    for cc in candidates {
+        let cc = cc.trim().to_uppercase();
+        if !cc.is_empty() && !countries.contains(&cc) {
+            countries.push(cc);
+        }
+    }
+    countries
+}
+
+/// Summarize which datasets are loaded and how many records each one enriched; `failed` names
+/// the requested datasets that were given up.
+pub fn dataset_status(
+    map: &HashMap<u32, AsInfoOut>,
+    opts: &LoadOptions,
+    failed: &[&str],
+) -> Vec<DatasetStatus> {
+    let count = |f: fn(&AsInfoOut) -> bool| map.values().filter(|v| f(v)).count();
+    let manifest = &opts.manifest;
+    let heavy = !opts.simplified;
+    let mut status = vec![
+        DatasetStatus {
+            name: "ripe",
+            loaded: true,
+            failed: false,
+            records: count(|v| !v.inner.name.is_empty()),
+        },
+        DatasetStatus {
+            name: "caida",
+            loaded: manifest.as2org.enabled,
+            failed: false,
+            records: count(|v| v.inner.as2org.is_some()),
+        },
+        DatasetStatus {
+            name: "population",
+            loaded: heavy && manifest.population.enabled,
+            failed: false,
+            records: count(|v| v.inner.population.is_some()),
+        },
+        DatasetStatus {
+            name: "hegemony",
+            loaded: heavy && manifest.hegemony.enabled,
+            failed: false,
+            records: count(|v| v.inner.hegemony.is_some()),
+        },
+        DatasetStatus {
+            name: "peeringdb",
+            loaded: heavy && manifest.peeringdb.enabled,
+            failed: false,
+            records: count(|v| v.inner.peeringdb.is_some()),
+        },
+        DatasetStatus {
+            name: "countries",
+            loaded: !opts.compact_country && opts.from_file.is_none() && manifest.countries.enabled,
+            failed: false,
+            records: count(|v| v.country_name.as_ref().is_some_and(|n| !n.is_empty())),
+        },
+    ];
+    for dataset in status.iter_mut() {
+        dataset.failed = failed.contains(&dataset.name);
+        dataset.loaded &= !dataset.failed;
+    }
+    status
+}
+
+/// Compute a strong ETag over all records in ASN order.
+pub fn dataset_etag(map: &HashMap<u32, AsInfoOut>) -> String {
+    let mut asns: Vec<&u32> = map.keys().collect();
+    asns.sort_unstable();
+    let mut hasher = Sha256::new();
+    for asn in asns {
+        if let Ok(bytes) = serde_json::to_vec(&map[asn]) {
+            hasher.update(&bytes);
+            hasher.update(b"\n");
+        }
+    }
+    format!("\"{}\"", hex::encode(hasher.finalize()))
+}
+
+/// Whether an `If-None-Match` header value matches `etag`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|t| t.trim().trim_start_matches("W/"))
+        .any(|t| t == "*" || t == etag)
+}
+
+fn build_index(map: &HashMap<u32, AsInfoOut>, key: fn(&As2orgInfo) -> String) -> OrgIndex {
+    let mut index: OrgIndex = HashMap::new();
+    for (asn, info) in map.iter() {
+        if let Some(as2org) = &info.inner.as2org {
+            let key = key(as2org);
+            if key.is_empty() {
+                continue;
+            }
+            index.entry(key).or_default().push(*asn);
+        }
+    }
+    for asns in index.values_mut() {
+        asns.sort_unstable();
+    }
+    index
+}
+
+/// ETag of a lookup response: the dataset ETag combined with what was requested, so it changes
+/// whenever the data is refreshed or annotations are reloaded.
+fn lookup_etag(dataset_etag: &str, request: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(dataset_etag.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(request);
+    format!("\"{}\"", hex::encode(hasher.finalize()))
+}
+
+/// Whether the request's `If-None-Match` header matches `etag`.
+fn not_modified(req_headers: &HeaderMap, etag: &str) -> bool {
+    req_headers
+        .get(http::header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| etag_matches(v, etag))
+}
+
+/// Headers shared by fresh and 304 lookup responses.
+fn lookup_cache_headers(state: &AppState, etag: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(http::header::ETAG, value);
+    }
+    if let Some(value) = state.cache_control() {
+        headers.insert(http::header::CACHE_CONTROL, value);
+    }
+    headers
+}
+
+/// Build the org name index used for org lookups; keys are lowercased org names.
+pub fn build_org_index(map: &HashMap<u32, AsInfoOut>) -> OrgIndex {
+    build_index(map, |v| v.org_name.trim().to_lowercase())
+}
+
+/// Build the org ID index used for sibling ASN lookups; keys are as2org org IDs.
+pub fn build_org_id_index(map: &HashMap<u32, AsInfoOut>) -> OrgIndex {
+    build_index(map, |v| v.org_id.trim().to_string())
+}
+
+/// Records of `old` that differ from their counterpart in `new`, keyed by ASN.
+///
+/// ASNs that are new or were removed are not included; only changed records have a meaningful
+/// previous value.
+fn changed_records(old: &RecordMap, new: &HashMap<u32, AsInfoOut>) -> HashMap<u32, AsInfoOut> {
+    old.asns()
+        .into_iter()
+        .filter_map(|asn| {
+            let new_info = new.get(&asn)?;
+            let old_info = old.get(&asn)?;
+            (json!(old_info) != json!(new_info)).then_some((asn, old_info))
+        })
+        .collect()
+}
+
+/// Serving data built from a fresh load, ready to replace the data in [`AppState`].
+struct Refreshed {
+    records: RecordMap,
+    org_index: OrgIndex,
+    org_id_index: OrgIndex,
+    etag: String,
+    datasets: Vec<DatasetStatus>,
+    previous: HashMap<u32, AsInfoOut>,
+    updated_at: String,
+}
+
+impl Refreshed {
+    /// Annotate freshly loaded records and derive everything served from them. `current` is the
+    /// snapshot being replaced, kept for `history=true`. CPU-bound, so run it off the workers.
+    fn build(
+        (mut map, updated_at, failed): LoadedRecords,
+        annotations: &Annotations,
+        current: &RecordMap,
+        opts: &LoadOptions,
+    ) -> Self {
+        apply_annotations(&mut map, annotations);
+        Refreshed {
+            org_index: build_org_index(&map),
+            org_id_index: build_org_id_index(&map),
+            etag: dataset_etag(&map),
+            datasets: dataset_status(&map, opts, &failed),
+            previous: changed_records(current, &map),
+            records: RecordMap::new(map, opts.compress_records),
+            updated_at,
+        }
+    }
+
+    /// Swap the new data in within a single critical section, so requests never see the map,
+    /// indexes, and updated_at out of step. Only pointer moves happen under the locks.
+    fn swap_into(self, state: &AppState) {
+        let mut map_guard = state.map.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: map mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut index_guard = state.org_index.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: org index mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut id_index_guard = state.org_id_index.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: org ID index mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut etag_guard = state.etag.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: etag mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut datasets_guard = state.datasets.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: datasets mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut previous_guard = state.previous.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: previous mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        let mut ts_guard = state.updated_at.lock().unwrap_or_else(|poisoned| {
+            error!("background updater: updated_at mutex is poisoned, recovering");
+            poisoned.into_inner()
+        });
+        *map_guard = self.records;
+        *index_guard = self.org_index;
+        *id_index_guard = self.org_id_index;
+        *etag_guard = self.etag;
+        *datasets_guard = self.datasets;
+        *previous_guard = self.previous;
+        *ts_guard = self.updated_at;
+    }
+}
+
+pub const MINIMUM_UPDATER_INTERVAL_SECS: u64 = 3600;
+const MINIMUM_ENTRY_TTL_SECS: u64 = 600;
+/// Delay before the updater retries when optional datasets failed to load.
+const PARTIAL_RETRY_SECS: u64 = 300;
+/// Upper bound for the widened refresh interval with [`RefreshEscalation::Backoff`].
+const MAXIMUM_BACKOFF_SECS: u64 = 7 * 24 * 3600;
 
-    Ok((out, updated_at))
+/// Exit code used when the updater gives up after too many consecutive refresh failures.
+pub const REFRESH_FAILED_EXIT_CODE: i32 = 14;
+
+/// What the updater does once refreshes keep failing.
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum RefreshEscalation {
+    /// Exit the process so an orchestrator restarts it
+    Exit,
+    /// Keep serving the last good data and double the refresh interval after each further failure
+    Backoff,
+}
+
+/// Escalation policy for consecutive refresh failures.
+#[derive(Debug, Clone, Copy)]
+pub struct RefreshFailurePolicy {
+    /// Consecutive failures before escalating; `None` retries on the normal interval forever
+    pub max_failures: Option<u32>,
+    pub escalation: RefreshEscalation,
 }
 
-const MINIMUM_UPDATER_INTERVAL_SECS: u64 = 3600;
+impl RefreshFailurePolicy {
+    /// Wait before the next refresh given the number of consecutive failures so far.
+    fn next_wait(&self, interval: Duration, failures: u32) -> Duration {
+        match (self.max_failures, self.escalation) {
+            (Some(max), RefreshEscalation::Backoff) if failures >= max => {
+                let exponent = (failures - max + 1).min(16);
+                interval
+                    .saturating_mul(1 << exponent)
+                    .min(Duration::from_secs(MAXIMUM_BACKOFF_SECS).max(interval))
+            }
+            _ => interval,
+        }
+    }
+}
 
 pub fn start_updater(
-    map: Arc<Mutex<HashMap<u32, AsInfoOut>>>,
-    updated_at: Arc<Mutex<String>>,
+    state: AppState,
     refresh_secs: u64,
-    simplified: bool,
+    entry_ttl_secs: Option<u64>,
+    opts: LoadOptions,
+    failure_policy: RefreshFailurePolicy,
+    data_age: Duration,
 ) -> JoinHandle<()> {
+    let interval = Duration::from_secs(refresh_secs.max(MINIMUM_UPDATER_INTERVAL_SECS)); // minimum 1 hour
+
+    // data loaded from the serve cache is already `data_age` old
+    let loaded_at = Instant::now()
+        .checked_sub(data_age)
+        .unwrap_or_else(Instant::now);
+    // set before spawning, so /health/ready never sees the initial data as unloaded
+    *state
+        .refresh_status
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = RefreshStatus {
+        last_success: Some(loaded_at),
+        last_failed: false,
+        stale_after: Some(interval * 2),
+    };
     tokio::spawn(async move {
-        let interval = Duration::from_secs(refresh_secs.max(MINIMUM_UPDATER_INTERVAL_SECS)); // minimum 1 hour
+        let entry_ttl = entry_ttl_secs.map(|s| Duration::from_secs(s.max(MINIMUM_ENTRY_TTL_SECS)));
+        let mut last_refresh = loaded_at;
+        let mut failures: u32 = 0;
         loop {
-            sleep(interval).await;
+            let mut wait = failure_policy.next_wait(interval, failures);
+            // retry early to complete a dataset that came up partial
+            let partial = state
+                .datasets
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .any(|d| d.failed);
+            if partial {
+                wait = wait.min(Duration::from_secs(PARTIAL_RETRY_SECS));
+            }
+            // a lookup can trigger an early refresh once the data is older than the entry TTL
+            let earliest = match (&state.refresh_hint, entry_ttl) {
+                (Some(_), Some(ttl)) => wait.min(ttl),
+                _ => wait,
+            };
+            *state.next_refresh.lock().unwrap_or_else(|e| e.into_inner()) =
+                Some(last_refresh + earliest);
+            match (&state.refresh_hint, entry_ttl) {
+                (Some(hint), Some(ttl)) => {
+                    tokio::select! {
+                        _ = sleep_until(last_refresh + wait) => {}
+                        _ = hint.notified() => {
+                            // queried, but only refresh early once the data is older than the TTL
+                            if last_refresh.elapsed() < ttl {
+                                continue;
+                            }
+                            info!("background updater: queried data is older than entry TTL");
+                        }
+                    }
+                }
+                _ => sleep_until(last_refresh + wait).await,
+            }
+            last_refresh = Instant::now();
             info!("background updater: refreshing ASN data ...");
-            match load_asn_map_out(simplified) {
-                Ok((new_map, ts)) => {
-                    // Update both map and updated_at within a single critical section
-                    // to avoid exposing an inconsistent state between them.
-                    let mut map_guard = map.lock().unwrap_or_else(|poisoned| {
-                        error!("background updater: map mutex is poisoned, recovering");
-                        poisoned.into_inner()
-                    });
-                    let mut ts_guard = updated_at.lock().unwrap_or_else(|poisoned| {
-                        error!("background updater: updated_at mutex is poisoned, recovering");
-                        poisoned.into_inner()
-                    });
-                    *map_guard = new_map;
-                    *ts_guard = ts;
+            // loading blocks, including the sleeps between retries, so keep it off the workers
+            let load_opts = opts.clone();
+            let loaded = tokio::task::spawn_blocking(move || load_asn_map_out(&load_opts, false))
+                .await
+                .unwrap_or_else(|e| {
+                    error!("background updater: load task failed: {e}");
+                    Err(1)
+                });
+            match loaded {
+                Ok(loaded) => {
+                    failures = 0;
+                    // build from a snapshot on the blocking pool; the writer lock keeps the
+                    // annotations watcher from swapping in records meanwhile
+                    let _writer = state.records_writer.lock().await;
+                    let annotations = state
+                        .annotations
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .clone();
+                    let current = state.map.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    let build_opts = opts.clone();
+                    let built = tokio::task::spawn_blocking(move || {
+                        Refreshed::build(loaded, &annotations, &current, &build_opts)
+                    })
+                    .await;
+                    match built {
+                        Ok(refreshed) => refreshed.swap_into(&state),
+                        Err(e) => {
+                            error!("background updater: build task failed: {e}");
+                            state
+                                .refresh_status
+                                .lock()
+                                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                                .last_failed = true;
+                            continue;
+                        }
+                    }
+                    state.metrics.mark_refreshed();
+                    let mut refresh_status = state
+                        .refresh_status
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner());
+                    refresh_status.last_success = Some(Instant::now());
+                    refresh_status.last_failed = false;
                     info!("background updater: ASN data updated");
                 }
                 Err(e) => {
-                    error!("background updater: refresh failed with code {e}");
+                    failures += 1;
+                    state
+                        .refresh_status
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .last_failed = true;
+                    error!(
+                        "background updater: refresh failed with code {e} ({failures} consecutive failures)"
+                    );
+                    if let Some(max) = failure_policy.max_failures.filter(|m| failures >= *m) {
+                        match failure_policy.escalation {
+                            RefreshEscalation::Exit => {
+                                error!(
+                                    "background updater: {failures} consecutive refresh failures (limit {max}), exiting"
+                                );
+                                std::process::exit(REFRESH_FAILED_EXIT_CODE);
+                            }
+                            RefreshEscalation::Backoff => {
+                                warn!(
+                                    "background updater: {failures} consecutive refresh failures (limit {max}), next attempt in {}s",
+                                    failure_policy.next_wait(interval, failures).as_secs()
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(pfx2as) = &state.pfx2as {
+                match Pfx2As::load() {
+                    Ok(new_pfx2as) => {
+                        *pfx2as.lock().unwrap_or_else(|e| e.into_inner()) = new_pfx2as;
+                        info!("background updater: prefix-to-origin data updated");
+                    }
+                    Err(e) => {
+                        error!("background updater: prefix-to-origin refresh failed with code {e}");
+                    }
                 }
             }
         }
     })
 }
 
-async fn health(State(state): State<AppState>) -> Json<Value> {
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "Server status and dataset timestamp", body = Value,
+            example = json!({"status": "ok", "updatedAt": "2025-10-29T00:00:00Z",
+                "datasets": ["ripe", "caida", "countries"], "failedDatasets": []}))
+    )
+)]
+async fn health(State(state): State<AppState>) -> (HeaderMap, Json<Value>) {
+    let record_count = state
+        .map
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record_count();
     let updated_at = state
         .updated_at
         .lock()
         .unwrap_or_else(|e| e.into_inner())
         .clone();
-    Json(json!({
-        "status": "ok",
-        "updatedAt": updated_at,
-    }))
+    let datasets = state.datasets.lock().unwrap_or_else(|e| e.into_inner());
+    let loaded: Vec<&str> = datasets
+        .iter()
+        .filter(|d| d.loaded)
+        .map(|d| d.name)
+        .collect();
+    let failed: Vec<&str> = datasets
+        .iter()
+        .filter(|d| d.failed)
+        .map(|d| d.name)
+        .collect();
+    drop(datasets);
+    let status = match failed.is_empty() {
+        true => "ok",
+        false => "degraded",
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(DATASET_TOTAL_HEADER, HeaderValue::from(record_count));
+    (
+        headers,
+        Json(json!({
+            "status": status,
+            "updatedAt": updated_at,
+            "datasets": loaded,
+            "failedDatasets": failed,
+        })),
+    )
 }
 
-fn convert_to_legacy(list: Vec<AsInfoOut>) -> Vec<Value> {
-    let mut out = Vec::with_capacity(list.len());
-    for o in list.into_iter() {
-        let asn = o.inner.asn;
-        let as_name = o.inner.name.clone();
-        let country_code = o.inner.country.clone();
-        let country_name = o.country_name.clone();
-        let org_id = o
-            .inner
-            .as2org
-            .as_ref()
-            .map(|v| v.org_id.clone())
-            .unwrap_or_default();
-        let org_name = o
-            .inner
-            .as2org
-            .as_ref()
-            .map(|v| v.org_name.clone())
-            .unwrap_or_default();
-        out.push(json!({
-            "asn": asn,
-            "as_name": as_name,
-            "org_id": org_id,
-            "org_name": org_name,
-            "country_code": country_code,
-            "country_name": country_name,
-            "data_source": "",
-        }));
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "The process is up; says nothing about the loaded data",
+            body = Value, example = json!({"status": "ok"}))
+    )
+)]
+async fn health_live() -> Json<Value> {
+    Json(json!({"status": "ok"}))
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Records are loaded, fresh, and the last refresh succeeded",
+            body = Value,
+            example = json!({"status": "ready", "records": 120000, "dataAgeSecs": 3600,
+                "lastRefreshFailed": false})),
+        (status = 503, description = "No records are loaded, the last refresh failed, or the data \
+            is older than twice the refresh interval", body = Value)
+    )
+)]
+async fn health_ready(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let records = state
+        .map
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record_count();
+    let status = state
+        .refresh_status
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    let age = status.last_success.map(|t| t.elapsed());
+    let reason = if records == 0 {
+        Some("no records loaded")
+    } else if age.is_none() {
+        Some("data has not been loaded successfully")
+    } else if status.last_failed {
+        Some("the last refresh failed")
+    } else if age
+        .zip(status.stale_after)
+        .is_some_and(|(age, max)| age > max)
+    {
+        Some("data is stale")
+    } else {
+        None
+    };
+    let code = match reason {
+        None => StatusCode::OK,
+        Some(_) => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    let mut body = json!({
+        "status": if reason.is_none() { "ready" } else { "not ready" },
+        "records": records,
+        "dataAgeSecs": age.map(|age| age.as_secs()),
+        "lastRefreshFailed": status.last_failed,
+    });
+    if let Some(reason) = reason {
+        body["reason"] = json!(reason);
     }
-    out
+    (code, Json(body))
 }
 
-async fn get_lookup(
+async fn health_upstream(
     State(state): State<AppState>,
-    Query(q): Query<LookupQuery>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    let asns: Vec<u32> = q
-        .asns
-        .clone()
-        .unwrap_or_default()
-        .split(',')
-        .filter_map(|s| s.trim().parse::<u32>().ok())
-        .collect();
-
-    if asns.is_empty() {
+    let Some(probe) = state.upstream_probe.clone() else {
         return Err((
             StatusCode::BAD_REQUEST,
-            Json(json!({"error": "no valid ASNs provided in 'asns' query parameter"})),
-        ));
-    }
-
-    if asns.len() > state.max_asns {
-        return Err((
-            StatusCode::PAYLOAD_TOO_LARGE,
             Json(
-                json!({"error": format!("payload too large, max ASNs per request is {}", state.max_asns)}),
+                json!({"error": "upstream probing is disabled, start the server with --probe-upstream"}),
             ),
         ));
+    };
+    let report = probe.report().await;
+    let status = match report.upstreams.iter().all(|u| u.reachable) {
+        true => "ok",
+        false => "degraded",
+    };
+    Ok(Json(json!({
+        "status": status,
+        "checkedAt": report.checked_at,
+        "upstreams": report.upstreams,
+    })))
+}
+
+/// Best-effort provenance of a record: the comma-separated datasets it draws on, e.g.
+/// `ripe,caida`.
+///
+/// bgpkit-commons does not record where each field came from, so this is derived from which
+/// datasets have data for the ASN: the AS name always comes from the RIPE NCC AS names list,
+/// `caida` means an as2org (org name/ID) record exists, and `peeringdb` a PeeringDB net record.
+pub fn data_source(info: &AsInfo) -> String {
+    let mut sources = vec!["ripe"];
+    if info.as2org.is_some() {
+        sources.push("caida");
+    }
+    if info.peeringdb.is_some() {
+        sources.push("peeringdb");
     }
+    sources.join(",")
+}
 
-    let map_guard = state.map.lock().map_err(|_| {
-        error!("get_lookup: map mutex is poisoned");
+/// Convert a record into the legacy flat schema shared by `?legacy=true` and `generate --legacy`.
+///
+/// The field set is a compatibility contract with existing consumers: `asn`, `as_name`,
+/// `country_code`, `country_name`, `data_source`, `org_id`, `org_name`, and nothing else. Keys
+/// come out in that (alphabetical) order because `serde_json` maps are sorted, as they always have
+/// been; enabling `serde_json/preserve_order` would change it.
+pub fn legacy_record(info: &AsInfo, country_name: Option<&str>) -> Value {
+    // org fields are null when no as2org record exists, so consumers can tell a missing
+    // org apart from one that is present but blank
+    let (org_id, org_name) = match info.as2org.as_ref() {
+        None => (Value::Null, Value::Null),
+        Some(v) => (json!(v.org_id), json!(v.org_name)),
+    };
+    json!({
+        "asn": info.asn,
+        "as_name": info.name,
+        "org_id": org_id,
+        "org_name": org_name,
+        "country_code": info.country,
+        "country_name": country_name,
+        "data_source": data_source(info),
+    })
+}
+
+async fn metrics(State(state): State<AppState>) -> Result<Response, (StatusCode, Json<Value>)> {
+    let record_count = state
+        .map
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .record_count();
+    Response::builder()
+        .header(
+            http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )
+        .body(Body::from(state.metrics.render(record_count)))
+        .map_err(|e| {
+            error!("metrics: failed to build response: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })
+}
+
+async fn datasets(State(state): State<AppState>) -> Json<Value> {
+    let datasets = state
+        .datasets
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    Json(json!({ "datasets": datasets }))
+}
+
+/// Batches up to this size are rendered inline on the request task.
+const INLINE_RENDER_MAX_RECORDS: usize = 32;
+
+/// Render looked-up records as a JSON response body.
+///
+/// Small batches are rendered inline. Larger ones are moved to the blocking pool so a batch at
+/// the `max_asns` limit does not stall the async workers, with `batch_permits` capping how many
+/// such batches run at once.
+async fn render_records(
+    state: &AppState,
+    found: Vec<AsInfoOut>,
+    legacy: bool,
+) -> Result<Value, (StatusCode, Json<Value>)> {
+    let inline = found.len() <= INLINE_RENDER_MAX_RECORDS;
+    let render = move || {
+        if legacy {
+            json!(convert_to_legacy(found))
+        } else {
+            json!(found)
+        }
+    };
+    if inline {
+        return Ok(render());
+    }
+    let internal_error = || {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "internal server error"})),
         )
+    };
+    let _permit = state.batch_permits.acquire().await.map_err(|_| {
+        error!("render_records: batch semaphore is closed");
+        internal_error()
     })?;
+    tokio::task::spawn_blocking(render).await.map_err(|e| {
+        error!("render_records: render task failed: {e}");
+        internal_error()
+    })
+}
 
-    let mut found = Vec::with_capacity(asns.len());
-    for asn in asns {
-        if let Some(info) = map_guard.get(&asn) {
-            found.push(info.clone());
+/// Attach each record's previous value under `previous`, index-aligned with the rendered array.
+///
+/// Records without a previous value (unchanged since the last refresh) get no `previous` key.
+fn add_previous(results: &mut Value, previous: Vec<Option<AsInfoOut>>, legacy: bool) {
+    let Some(records) = results.as_array_mut() else {
+        return;
+    };
+    for (record, prev) in records.iter_mut().zip(previous) {
+        if let Some(prev) = prev {
+            record["previous"] = match legacy {
+                true => legacy_record(&prev.inner, prev.country_name.as_deref()),
+                false => json!(prev),
+            };
         }
     }
+}
 
-    let use_legacy = q.legacy.unwrap_or(false);
-    let results = if use_legacy {
-        json!(convert_to_legacy(found))
-    } else {
-        json!(found)
+fn convert_to_legacy(list: Vec<AsInfoOut>) -> Vec<Value> {
+    list.iter()
+        .map(|o| legacy_record(&o.inner, o.country_name.as_deref()))
+        .collect()
+}
+
+/// Parse a comma-separated ASN list, accepting an optional case-insensitive `AS` or `ASN` prefix
+/// (e.g. `AS13335`). Returns the parsed ASNs and the tokens that failed to parse; empty tokens
+/// are ignored.
+fn parse_asns(raw: &str) -> (Vec<u32>, Vec<String>) {
+    let mut asns = Vec::new();
+    let mut invalid = Vec::new();
+    for token in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let upper = token.to_ascii_uppercase();
+        let digits = upper
+            .strip_prefix("ASN")
+            .or_else(|| upper.strip_prefix("AS"))
+            .unwrap_or(&upper);
+        match digits.parse::<u32>() {
+            Ok(asn) => asns.push(asn),
+            Err(_) => invalid.push(token.to_string()),
+        }
+    }
+    (asns, invalid)
+}
+
+#[utoipa::path(
+    get,
+    path = "/lookup",
+    params(LookupQuery),
+    responses(
+        (status = 200, description = "Found records in request order; a `LookupResponse` envelope \
+            when `page` or `page_size` is set, or `{found, missing}` with `include_missing=true`",
+            body = Vec<AsInfoOut>),
+        (status = 304, description = "The `If-None-Match` ETag matches the current response"),
+        (status = 400, description = "Invalid ASNs, unknown `fields`, or other invalid parameters"),
+        (status = 401, description = "Missing or invalid bearer token when `ASNINFO_API_TOKEN` is set"),
+        (status = 413, description = "More ASNs than `ASNINFO_MAX_ASNS`, or than `limit` \
+            (capped at `ASNINFO_MAX_ASNS_CEILING`) when set")
+    )
+)]
+async fn get_lookup(
+    State(state): State<AppState>,
+    Query(q): Query<LookupQuery>,
+    RawQuery(raw_query): RawQuery,
+    req_headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let fields = parse_fields(q.fields.as_deref())?;
+    if fields.is_some() && q.legacy.unwrap_or(false) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "'fields' cannot be combined with legacy=true"})),
+        ));
+    }
+    let (asns, invalid) = parse_asns(q.asns.as_deref().unwrap_or_default());
+    if !invalid.is_empty() && !q.lenient.unwrap_or(false) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "invalid ASNs in 'asns' query parameter", "invalid": invalid})),
+        ));
+    }
+
+    if asns.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "no valid ASNs provided in 'asns' query parameter"})),
+        ));
+    }
+
+    let max_asns = state.asn_limit(q.limit);
+    if asns.len() > max_asns {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(
+                json!({"error": format!("payload too large, max ASNs per request is {max_asns}")}),
+            ),
+        ));
+    }
+
+    let at_ix = q.at_ix.unwrap_or(false);
+    if at_ix && !state.with_ix {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(
+                json!({"error": "IX presence data is not loaded, start the server with --with-ix"}),
+            ),
+        ));
+    }
+
+    state.hint_refresh();
+
+    // the std mutex guard must not be held across the render await
+    let (mut found, missing, record_count, mut headers) = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("get_lookup: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+
+        // read the dataset etag under the map lock so it matches the records being returned
+        let etag = lookup_etag(
+            &state.etag.lock().unwrap_or_else(|e| e.into_inner()),
+            raw_query.unwrap_or_default().as_bytes(),
+        );
+        let headers = lookup_cache_headers(&state, &etag);
+        if not_modified(&req_headers, &etag) {
+            return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+        }
+
+        let requested = asns.len();
+        let mut hits = 0;
+        let mut found = Vec::with_capacity(asns.len());
+        let mut missing = Vec::new();
+        for asn in asns {
+            let Some(info) = map_guard.get(&asn) else {
+                missing.push(asn);
+                continue;
+            };
+            hits += 1;
+            if at_ix && info.ix_count.unwrap_or(0) == 0 {
+                continue;
+            }
+            found.push(info);
+        }
+        state.metrics.record_lookup(hits, requested - hits);
+        (found, missing, map_guard.record_count(), headers)
     };
 
-    Ok(Json(results))
+    // pagination is opt-in so plain lookups keep returning a bare array
+    let page = (q.page.is_some() || q.page_size.is_some())
+        .then(|| Page::new(q.page, q.page_size, state.max_asns));
+    let count = found.len();
+    if let Some(page) = page {
+        found = page.slice(found);
+    }
+
+    let previous: Vec<Option<AsInfoOut>> = match q.history.unwrap_or(false) {
+        true => {
+            let previous_guard = state.previous.lock().unwrap_or_else(|e| e.into_inner());
+            found
+                .iter()
+                .map(|info| previous_guard.get(&info.inner.asn).cloned())
+                .collect()
+        }
+        false => Vec::new(),
+    };
+
+    headers.insert(DATASET_TOTAL_HEADER, HeaderValue::from(record_count));
+    let updated_at = state
+        .updated_at
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+
+    let use_legacy = q.legacy.unwrap_or(false);
+    let mut results = render_records(&state, found, use_legacy).await?;
+    if let Some(fields) = &fields {
+        project_fields(&mut results, fields);
+    }
+    add_previous(&mut results, previous, use_legacy);
+    if q.include_missing.unwrap_or(false) {
+        results = json!({"found": results, "missing": missing});
+    }
+
+    let body = match page {
+        Some(page) => json!(page.response(count, updated_at, results)),
+        None => results,
+    };
+    Ok((headers, Json(body)).into_response())
 }
 
+#[utoipa::path(
+    post,
+    path = "/lookup",
+    request_body = LookupBody,
+    responses(
+        (status = 200, description = "Found records in request order", body = Vec<AsInfoOut>),
+        (status = 304, description = "The `If-None-Match` ETag matches the current response"),
+        (status = 400, description = "No ASNs in the request body, or unknown `fields`"),
+        (status = 401, description = "Missing or invalid bearer token when `ASNINFO_API_TOKEN` is set"),
+        (status = 413, description = "More ASNs than `ASNINFO_MAX_ASNS`, or than `limit` \
+            (capped at `ASNINFO_MAX_ASNS_CEILING`) when set")
+    )
+)]
 async fn post_lookup(
     State(state): State<AppState>,
+    req_headers: HeaderMap,
     Json(body): Json<LookupBody>,
-) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+) -> Result<Response, (StatusCode, Json<Value>)> {
     if body.asns.is_empty() {
         return Err((
             StatusCode::BAD_REQUEST,
             Json(json!({"error": "no ASNs provided in request body"})),
         ));
     }
-    if body.asns.len() > state.max_asns {
+    let fields = parse_fields(body.fields.as_deref())?;
+    let max_asns = state.asn_limit(body.limit);
+    if body.asns.len() > max_asns {
         return Err((
             StatusCode::PAYLOAD_TOO_LARGE,
             Json(
-                json!({"error": format!("payload too large, max ASNs per request is {}", state.max_asns)}),
+                json!({"error": format!("payload too large, max ASNs per request is {max_asns}")}),
             ),
         ));
     }
 
+    state.hint_refresh();
+
+    let requested = body.asns.len();
+    let (found, record_count, mut headers) = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("post_lookup: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+
+        // the field list changes the response, so it is part of the ETag key
+        let mut request_key: Vec<u8> = body.asns.iter().flat_map(|asn| asn.to_be_bytes()).collect();
+        if let Some(fields) = &fields {
+            request_key.extend_from_slice(fields.join(",").as_bytes());
+        }
+        let etag = lookup_etag(
+            &state.etag.lock().unwrap_or_else(|e| e.into_inner()),
+            &request_key,
+        );
+        let headers = lookup_cache_headers(&state, &etag);
+        if not_modified(&req_headers, &etag) {
+            return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+        }
+
+        let mut found = Vec::with_capacity(body.asns.len());
+        for asn in body.asns {
+            if let Some(info) = map_guard.get(&asn) {
+                found.push(info);
+            }
+        }
+        (found, map_guard.record_count(), headers)
+    };
+    state
+        .metrics
+        .record_lookup(found.len(), requested - found.len());
+    headers.insert(DATASET_TOTAL_HEADER, HeaderValue::from(record_count));
+
+    let mut results = render_records(&state, found, false).await?;
+    if let Some(fields) = &fields {
+        project_fields(&mut results, fields);
+    }
+    Ok((headers, Json(results)).into_response())
+}
+
+/// Stream the records of an arbitrarily large `asns` body as JSONL, in request order.
+///
+/// Unlike `POST /lookup`, this is not capped by `max_asns`: records are rendered chunk by chunk
+/// as the client reads them, so neither the records nor the response are held in memory at once.
+async fn post_lookup_stream(
+    State(state): State<AppState>,
+    Json(body): Json<LookupBody>,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    if body.asns.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "no ASNs provided in request body"})),
+        ));
+    }
+    state.hint_refresh();
+
+    let records = state
+        .map
+        .lock()
+        .map_err(|_| {
+            error!("post_lookup_stream: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?
+        .clone();
+
+    Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+        .body(ndjson_body(records, body.asns))
+        .map_err(|e| {
+            error!("post_lookup_stream: failed to build response: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })
+}
+
+async fn post_org_lookup(
+    State(state): State<AppState>,
+    Json(body): Json<OrgLookupBody>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    if body.orgs.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "no org names provided in request body"})),
+        ));
+    }
+    if body.orgs.len() > state.max_asns {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(
+                json!({"error": format!("payload too large, max orgs per request is {}", state.max_asns)}),
+            ),
+        ));
+    }
+
+    let map_guard = state.map.lock().map_err(|_| {
+        error!("post_org_lookup: map mutex is poisoned");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "internal server error"})),
+        )
+    })?;
+    let index_guard = state.org_index.lock().map_err(|_| {
+        error!("post_org_lookup: org index mutex is poisoned");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "internal server error"})),
+        )
+    })?;
+
+    let mut results = serde_json::Map::with_capacity(body.orgs.len());
+    for org in body.orgs {
+        let asns = index_guard
+            .get(&org.trim().to_lowercase())
+            .cloned()
+            .unwrap_or_default();
+        let records: Vec<AsInfoOut> = asns.iter().filter_map(|asn| map_guard.get(asn)).collect();
+        results.insert(org, json!({"asns": asns, "records": records}));
+    }
+
+    Ok(Json(Value::Object(results)))
+}
+
+/// All ASNs registered under an as2org org ID, with their records.
+async fn get_org(
+    State(state): State<AppState>,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    state.hint_refresh();
+
     let map_guard = state.map.lock().map_err(|_| {
-        error!("post_lookup: map mutex is poisoned");
+        error!("get_org: map mutex is poisoned");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "internal server error"})),
+        )
+    })?;
+    let index_guard = state.org_id_index.lock().map_err(|_| {
+        error!("get_org: org ID index mutex is poisoned");
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(json!({"error": "internal server error"})),
         )
     })?;
 
-    let mut found = Vec::with_capacity(body.asns.len());
-    for asn in body.asns {
-        if let Some(info) = map_guard.get(&asn) {
-            found.push(info.clone());
+    let Some(asns) = index_guard.get(org_id.trim()).cloned() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("unknown org_id {org_id}")})),
+        ));
+    };
+    drop(index_guard);
+    let records: Vec<AsInfoOut> = asns.iter().filter_map(|asn| map_guard.get(asn)).collect();
+    drop(map_guard);
+
+    let org_name = records
+        .iter()
+        .find_map(|info| info.inner.as2org.as_ref())
+        .map(|v| v.org_name.clone());
+    Ok(Json(json!({
+        "org_id": org_id,
+        "org_name": org_name,
+        "asns": asns,
+        "records": records,
+    })))
+}
+
+/// Find records whose AS name or as2org org name contains `q`, case-insensitively.
+///
+/// This scans every record, so results are capped at `max_asns` and returned in ASN order.
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching records in ASN order", body = Vec<AsInfoOut>),
+        (status = 400, description = "No search term in `q`"),
+        (status = 401, description = "Missing or invalid bearer token when `ASNINFO_API_TOKEN` is set")
+    )
+)]
+async fn search(
+    State(state): State<AppState>,
+    Query(q): Query<SearchQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let filter = SearchFilter::new(q.q, None, None, q.country);
+    if filter.name_or_org.is_none() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "no search term provided in 'q' query parameter"})),
+        ));
+    }
+    run_search(&state, &filter, q.limit).await
+}
+
+/// Find records matching every filter in the JSON body, e.g.
+/// `{"name_contains": "cloud", "country": "US", "limit": 50}`.
+///
+/// Matching is the same as `GET /search`; results are capped at `max_asns` and returned in ASN
+/// order.
+#[utoipa::path(
+    post,
+    path = "/search",
+    request_body = SearchBody,
+    responses(
+        (status = 200, description = "Matching records in ASN order", body = Vec<AsInfoOut>),
+        (status = 400, description = "No filter in the request body"),
+        (status = 401, description = "Missing or invalid bearer token when `ASNINFO_API_TOKEN` is set")
+    )
+)]
+async fn post_search(
+    State(state): State<AppState>,
+    Json(body): Json<SearchBody>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let filter = SearchFilter::new(None, body.name_contains, body.org_contains, body.country);
+    if filter.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "no filter provided, set at least one of name_contains, org_contains, country"
+            })),
+        ));
+    }
+    run_search(&state, &filter, body.limit).await
+}
+
+/// Scan every record for `filter` and render up to `limit` matches (capped at `max_asns`).
+async fn run_search(
+    state: &AppState,
+    filter: &SearchFilter,
+    limit: Option<usize>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let limit = limit.unwrap_or(state.max_asns).min(state.max_asns);
+
+    state.hint_refresh();
+
+    // the std mutex guard must not be held across the render await
+    let mut found = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("search: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+        map_guard.matching(|info| filter.matches(info))
+    };
+
+    found.sort_unstable_by_key(|info| info.inner.asn);
+    found.truncate(limit);
+    let results = render_records(state, found, false).await?;
+    Ok(Json(results))
+}
+
+/// All records registered in a country, in ASN order, one page at a time.
+///
+/// Pages are capped at `max_asns` records since a country can have thousands of ASNs.
+async fn by_country(
+    State(state): State<AppState>,
+    Query(q): Query<CountryQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let code = q.code.unwrap_or_default().trim().to_uppercase();
+    if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "'code' must be a two-letter ISO country code"})),
+        ));
+    }
+    let page = Page::new(q.page, q.page_size, state.max_asns);
+
+    state.hint_refresh();
+
+    // the std mutex guard must not be held across the render await
+    let (mut found, updated_at) = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("by_country: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+        let found = map_guard.matching(|info| info.inner.country.eq_ignore_ascii_case(&code));
+        let updated_at = state
+            .updated_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        (found, updated_at)
+    };
+
+    found.sort_unstable_by_key(|info| info.inner.asn);
+    let count = found.len();
+    let results = render_records(&state, page.slice(found), false).await?;
+    Ok(Json(json!(page.response(count, updated_at, results))))
+}
+
+/// All records with an ASN in the inclusive range `start..=end`, in ASN order, one page at a time.
+///
+/// The range is probed ASN by ASN rather than scanning every record, and only the records of the
+/// requested page are fetched, so the span is capped at [`MAX_RANGE_SPAN`].
+async fn range(
+    State(state): State<AppState>,
+    Query(q): Query<RangeQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (Some(start), Some(end)) = (q.start, q.end) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "'start' and 'end' must both be valid ASNs"})),
+        ));
+    };
+    if end < start {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "'end' must not be less than 'start'"})),
+        ));
+    }
+    if end - start >= MAX_RANGE_SPAN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("range too large, max span is {MAX_RANGE_SPAN} ASNs")})),
+        ));
+    }
+    let page = Page::new(q.page, q.page_size, state.max_asns);
+
+    state.hint_refresh();
+
+    // the std mutex guard must not be held across the render await
+    let (count, found, updated_at) = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("range: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+        let asns: Vec<u32> = (start..=end)
+            .filter(|asn| map_guard.contains(asn))
+            .collect();
+        let count = asns.len();
+        let found: Vec<AsInfoOut> = page
+            .slice(asns)
+            .iter()
+            .filter_map(|asn| map_guard.get(asn))
+            .collect();
+        let updated_at = state
+            .updated_at
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        (count, found, updated_at)
+    };
+
+    let results = render_records(&state, found, false).await?;
+    Ok(Json(json!(page.response(count, updated_at, results))))
+}
+
+/// Return the records of the origin ASNs of the longest announced prefix covering an IP address.
+///
+/// Multi-origin prefixes return every origin; an address without a covering prefix is a 404.
+async fn get_lookup_ip(
+    State(state): State<AppState>,
+    Query(q): Query<IpQuery>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Some(pfx2as) = state.pfx2as.clone() else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(
+                json!({"error": "IP-to-ASN data is not loaded, start the server with --with-pfx2as"}),
+            ),
+        ));
+    };
+    let Some(ip) = q.addr.and_then(|a| a.trim().parse::<IpAddr>().ok()) else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "'addr' must be a valid IPv4 or IPv6 address"})),
+        ));
+    };
+
+    let asns = pfx2as.lock().unwrap_or_else(|e| e.into_inner()).origins(ip);
+    if asns.is_empty() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": format!("no announced prefix covers {ip}")})),
+        ));
+    }
+
+    state.hint_refresh();
+
+    let map_guard = state.map.lock().map_err(|_| {
+        error!("get_lookup_ip: map mutex is poisoned");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "internal server error"})),
+        )
+    })?;
+    let records: Vec<AsInfoOut> = asns.iter().filter_map(|asn| map_guard.get(asn)).collect();
+    drop(map_guard);
+
+    Ok(Json(json!({
+        "ip": ip,
+        "asns": asns,
+        "records": records,
+    })))
+}
+
+/// Resolve a domain's A/AAAA records and return the distinct records of the ASNs originating them.
+///
+/// Every resolved address is mapped to the origin ASNs of its longest covering prefix, so CDNs and
+/// multi-homed names may yield several ASNs. Addresses without a covering prefix are listed with
+/// an empty `asns` array.
+async fn get_domain(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let Some(pfx2as) = state.pfx2as.clone() else {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(
+                json!({"error": "IP-to-ASN data is not loaded, start the server with --with-pfx2as"}),
+            ),
+        ));
+    };
+
+    let mut ips: Vec<IpAddr> = match tokio::net::lookup_host((name.as_str(), 0)).await {
+        Ok(addrs) => addrs.map(|a| a.ip()).collect(),
+        Err(e) => {
+            info!("get_domain: failed to resolve {name}: {e}");
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": format!("failed to resolve domain {name}")})),
+            ));
+        }
+    };
+    ips.sort_unstable();
+    ips.dedup();
+
+    let mut asns: BTreeSet<u32> = BTreeSet::new();
+    let addresses: Vec<Value> = {
+        let pfx2as_guard = pfx2as.lock().unwrap_or_else(|e| e.into_inner());
+        ips.iter()
+            .map(|ip| {
+                let origins = pfx2as_guard.origins(*ip);
+                asns.extend(&origins);
+                json!({"ip": ip, "asns": origins})
+            })
+            .collect()
+    };
+
+    state.hint_refresh();
+
+    let map_guard = state.map.lock().map_err(|_| {
+        error!("get_domain: map mutex is poisoned");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "internal server error"})),
+        )
+    })?;
+    let records: Vec<AsInfoOut> = asns.iter().filter_map(|asn| map_guard.get(asn)).collect();
+    drop(map_guard);
+
+    Ok(Json(json!({
+        "domain": name,
+        "addresses": addresses,
+        "records": records,
+    })))
+}
+
+/// JSONL body with the records of `asns` in `records`, in order; ASNs without a record are
+/// skipped.
+///
+/// `records` is a snapshot of the map taken when the response started, so a refresh during a
+/// long download does not mix records of two datasets. Records are serialized lazily in chunks of
+/// [`DUMP_CHUNK_RECORDS`]; the next chunk is only produced once the previous one has been handed
+/// to the connection, so a slow client holds at most one chunk in memory instead of the whole
+/// response.
+fn ndjson_body(records: RecordMap, asns: Vec<u32>) -> Body {
+    let stream =
+        futures::stream::unfold((records, asns, 0usize), |(records, asns, pos)| async move {
+            if pos >= asns.len() {
+                return None;
+            }
+            let end = (pos + DUMP_CHUNK_RECORDS).min(asns.len());
+            let mut buf: Vec<u8> = Vec::new();
+            for asn in &asns[pos..end] {
+                if let Some(info) = records.get(asn) {
+                    match serde_json::to_vec(&info) {
+                        Ok(line) => {
+                            buf.extend_from_slice(&line);
+                            buf.push(b'\n');
+                        }
+                        Err(e) => error!("failed to serialize AS{asn}: {e}"),
+                    }
+                }
+            }
+            Some((
+                Ok::<_, std::convert::Infallible>(Bytes::from(buf)),
+                (records, asns, end),
+            ))
+        });
+    Body::from_stream(stream)
+}
+
+/// Stream the whole dataset as JSONL, ordered by ASN.
+async fn dump(
+    State(state): State<AppState>,
+    req_headers: HeaderMap,
+) -> Result<Response, (StatusCode, Json<Value>)> {
+    let (records, etag): (RecordMap, String) = {
+        let map_guard = state.map.lock().map_err(|_| {
+            error!("dump: map mutex is poisoned");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })?;
+        // read the etag under the map lock so it matches the snapshot being streamed
+        let etag = state.etag.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        (map_guard.clone(), etag)
+    };
+
+    if not_modified(&req_headers, &etag) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(http::header::ETAG, &etag)
+            .body(Body::empty())
+            .map_err(|e| {
+                error!("dump: failed to build response: {e}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "internal server error"})),
+                )
+            });
+    }
+    let mut asns = records.asns();
+    asns.sort_unstable();
+
+    Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/x-ndjson")
+        .header(http::header::ETAG, &etag)
+        .body(ndjson_body(records, asns))
+        .map_err(|e| {
+            error!("dump: failed to build response: {e}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "internal server error"})),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn as_info(asn: u32, as2org: Option<As2orgInfo>) -> AsInfo {
+        AsInfo {
+            asn,
+            name: format!("AS{asn}-NAME"),
+            country: "US".to_string(),
+            as2org,
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        }
+    }
+
+    fn as2org(org_id: &str, org_name: &str) -> As2orgInfo {
+        As2orgInfo {
+            name: "AS-NAME".to_string(),
+            country: "US".to_string(),
+            org_id: org_id.to_string(),
+            org_name: org_name.to_string(),
+        }
+    }
+
+    fn record(asn: u32, name: &str, country: &str, org_name: Option<&str>) -> AsInfoOut {
+        let inner = AsInfo {
+            name: name.to_string(),
+            country: country.to_string(),
+            ..as_info(asn, org_name.map(|o| as2org("ORG-1", o)))
+        };
+        AsInfoOut {
+            data_source: data_source(&inner),
+            inner,
+            country_name: None,
+            continent: None,
+            countries: None,
+            ix_count: None,
+            ixs: None,
+            note: None,
+        }
+    }
+
+    /// Serving state over `records`, as set up by `serve` without optional features.
+    fn test_state(records: Vec<AsInfoOut>, max_asns: usize, max_asns_ceiling: usize) -> AppState {
+        let map: HashMap<u32, AsInfoOut> = records.into_iter().map(|r| (r.inner.asn, r)).collect();
+        AppState {
+            org_index: Arc::new(Mutex::new(build_org_index(&map))),
+            org_id_index: Arc::new(Mutex::new(build_org_id_index(&map))),
+            etag: Arc::new(Mutex::new(dataset_etag(&map))),
+            map: Arc::new(Mutex::new(RecordMap::new(map, false))),
+            annotations: Arc::new(Mutex::new(Annotations::default())),
+            datasets: Arc::new(Mutex::new(Vec::new())),
+            previous: Arc::new(Mutex::new(HashMap::new())),
+            updated_at: Arc::new(Mutex::new("2026-01-01T00:00:00.000Z".to_string())),
+            max_asns,
+            max_asns_ceiling,
+            with_ix: false,
+            batch_permits: Arc::new(Semaphore::new(1)),
+            refresh_hint: None,
+            pfx2as: None,
+            upstream_probe: None,
+            metrics: Arc::new(Metrics::new()),
+            next_refresh: Arc::new(Mutex::new(None)),
+            api_token: None,
+            rate_limiter: None,
+            refresh_status: Arc::new(Mutex::new(RefreshStatus::default())),
+            records_writer: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    fn search_state() -> AppState {
+        let records = vec![
+            record(64498, "CLOUD-B", "US", Some("Cloud Inc")),
+            record(64496, "Cloud-A", "US", None),
+            record(64497, "CLOUD-DE", "DE", Some("Cloud GmbH")),
+            record(64499, "OTHER", "US", Some("Cloudy Org")),
+        ];
+        test_state(records, 2, 2)
+    }
+
+    fn search_body(body: Value) -> SearchBody {
+        serde_json::from_value(body).unwrap()
+    }
+
+    /// ASNs returned by `POST /search` with `body`, in response order.
+    async fn post_search_asns(body: Value) -> Vec<u64> {
+        let Json(found) = post_search(State(search_state()), Json(search_body(body)))
+            .await
+            .unwrap();
+        found
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["asn"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn post_search_combines_filters_with_and() {
+        let found = post_search_asns(json!({"name_contains": "cloud", "country": "us"})).await;
+        assert_eq!(found, vec![64496, 64498]);
+        let found =
+            post_search_asns(json!({"name_contains": "cloud", "org_contains": "gmbh"})).await;
+        assert_eq!(found, vec![64497]);
+
+        // in ASN order, and capped at ASNINFO_MAX_ASNS (2) rather than the requested limit
+        let found = post_search_asns(json!({"org_contains": "cloud", "limit": 10})).await;
+        assert_eq!(found, vec![64497, 64498]);
+        let found = post_search_asns(json!({"org_contains": "cloud", "limit": 1})).await;
+        assert_eq!(found, vec![64497]);
+    }
+
+    #[tokio::test]
+    async fn post_search_rejects_empty_and_unknown_filters() {
+        for body in [json!({}), json!({"name_contains": "  ", "limit": 5})] {
+            let (status, _) = post_search(State(search_state()), Json(search_body(body)))
+                .await
+                .unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+        assert!(serde_json::from_value::<SearchBody>(json!({"name": "cloud"})).is_err());
+    }
+
+    #[test]
+    fn asn_limit_is_capped_at_the_ceiling() {
+        let state = test_state(Vec::new(), 2, 5);
+        assert_eq!(state.asn_limit(None), 2);
+        assert_eq!(state.asn_limit(Some(1)), 1);
+        assert_eq!(state.asn_limit(Some(4)), 4);
+        assert_eq!(state.asn_limit(Some(50)), 5);
+        // without a ceiling above ASNINFO_MAX_ASNS, the limit cannot be raised
+        assert_eq!(test_state(Vec::new(), 2, 2).asn_limit(Some(50)), 2);
+    }
+
+    async fn lookup_status(state: &AppState, asns: &[u32], limit: Option<usize>) -> StatusCode {
+        let body = serde_json::from_value(json!({"asns": asns, "limit": limit})).unwrap();
+        match post_lookup(State(state.clone()), HeaderMap::new(), Json(body)).await {
+            Ok(response) => response.status(),
+            Err((status, _)) => status,
+        }
+    }
+
+    async fn get_lookup_status(state: &AppState, query: &str) -> StatusCode {
+        let uri: http::Uri = format!("/lookup?{query}").parse().unwrap();
+        let Query(q) = Query::try_from_uri(&uri).unwrap();
+        let raw = RawQuery(Some(query.to_string()));
+        match get_lookup(State(state.clone()), Query(q), raw, HeaderMap::new()).await {
+            Ok(response) => response.status(),
+            Err((status, _)) => status,
+        }
+    }
+
+    #[tokio::test]
+    async fn lookup_limit_raises_the_asn_cap_up_to_the_ceiling() {
+        let records = (64496..64502).map(|asn| record(asn, "NAME", "US", None));
+        let state = test_state(records.collect(), 2, 4);
+        let asns = [64496, 64497, 64498];
+        assert_eq!(
+            lookup_status(&state, &asns, None).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(lookup_status(&state, &asns, Some(3)).await, StatusCode::OK);
+        let five = [64496, 64497, 64498, 64499, 64500];
+        assert_eq!(
+            lookup_status(&state, &five, Some(10)).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let query = "asns=64496,64497,64498";
+        assert_eq!(
+            get_lookup_status(&state, query).await,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        let query = "asns=64496,64497,64498&limit=3";
+        assert_eq!(get_lookup_status(&state, query).await, StatusCode::OK);
+    }
+
+    async fn readiness(state: &AppState, status: RefreshStatus) -> (StatusCode, Value) {
+        *state.refresh_status.lock().unwrap() = status;
+        let (code, Json(body)) = health_ready(State(state.clone())).await;
+        (code, body)
+    }
+
+    #[tokio::test]
+    async fn ready_only_after_a_successful_fresh_load() {
+        let state = test_state(vec![record(64496, "NAME", "US", None)], 10, 10);
+        let fresh = RefreshStatus {
+            last_success: Some(Instant::now()),
+            last_failed: false,
+            stale_after: Some(Duration::from_secs(3600)),
+        };
+        let (code, body) = readiness(&state, fresh.clone()).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["records"], 1);
+
+        // the previous data is still served, but the replica is not ready after a failed refresh
+        let failed = RefreshStatus {
+            last_failed: true,
+            ..fresh.clone()
+        };
+        let (code, body) = readiness(&state, failed).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "the last refresh failed");
+        assert_eq!(body["lastRefreshFailed"], true);
+
+        let stale = RefreshStatus {
+            stale_after: Some(Duration::ZERO),
+            last_success: Instant::now().checked_sub(Duration::from_secs(1)),
+            ..fresh.clone()
+        };
+        let (code, body) = readiness(&state, stale).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "data is stale");
+
+        let (code, body) = readiness(&state, RefreshStatus::default()).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "data has not been loaded successfully");
+
+        let empty = test_state(Vec::new(), 10, 10);
+        let (code, body) = readiness(&empty, fresh).await;
+        assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["reason"], "no records loaded");
+    }
+
+    #[test]
+    fn legacy_record_nulls_org_fields_without_as2org() {
+        let record = legacy_record(&as_info(64496, None), Some("United States"));
+        assert_eq!(record["org_id"], Value::Null);
+        assert_eq!(record["org_name"], Value::Null);
+        assert_eq!(record["as_name"], "AS64496-NAME");
+        assert_eq!(record["country_name"], "United States");
+    }
+
+    #[test]
+    fn legacy_record_keeps_blank_org_fields_from_as2org() {
+        let record = legacy_record(&as_info(64496, Some(as2org("", ""))), None);
+        assert_eq!(record["org_id"], "");
+        assert_eq!(record["org_name"], "");
+
+        let record = legacy_record(&as_info(64496, Some(as2org("ORG-1", "Example"))), None);
+        assert_eq!(record["org_id"], "ORG-1");
+        assert_eq!(record["org_name"], "Example");
+    }
+
+    #[test]
+    fn full_record_uses_null_for_missing_values() {
+        let out = AsInfoOut {
+            inner: as_info(64496, None),
+            country_name: None,
+            continent: None,
+            countries: None,
+            ix_count: None,
+            ixs: None,
+            note: None,
+            data_source: data_source(&as_info(64496, None)),
+        };
+        let value = json!(out);
+        for key in ["as2org", "population", "hegemony", "peeringdb"] {
+            assert_eq!(value[key], Value::Null, "{key}");
+        }
+        assert!(value.get("country_name").is_none());
+
+        let legacy = convert_to_legacy(vec![out]);
+        assert_eq!(legacy[0]["country_name"], Value::Null);
+        assert_eq!(legacy[0]["org_id"], Value::Null);
+    }
+
+    #[test]
+    fn legacy_output_has_the_historical_keys_in_order() {
+        let out = |info: AsInfo, country_name: Option<&str>| AsInfoOut {
+            data_source: data_source(&info),
+            inner: info,
+            country_name: country_name.map(str::to_string),
+            continent: Some("NA".to_string()),
+            countries: Some(vec!["US".to_string()]),
+            ix_count: Some(1),
+            ixs: Some(vec!["IX".to_string()]),
+            note: Some("note".to_string()),
+        };
+        let legacy = convert_to_legacy(vec![
+            out(
+                as_info(64496, Some(as2org("ORG-1", "Example"))),
+                Some("United States"),
+            ),
+            out(as_info(64497, None), None),
+        ]);
+        assert_eq!(
+            serde_json::to_string(&legacy).unwrap(),
+            "[{\"as_name\":\"AS64496-NAME\",\"asn\":64496,\"country_code\":\"US\",\
+             \"country_name\":\"United States\",\"data_source\":\"ripe,caida\",\
+             \"org_id\":\"ORG-1\",\"org_name\":\"Example\"},\
+             {\"as_name\":\"AS64497-NAME\",\"asn\":64497,\"country_code\":\"US\",\
+             \"country_name\":null,\"data_source\":\"ripe\",\"org_id\":null,\"org_name\":null}]"
+        );
+    }
+
+    #[test]
+    fn parse_asns_splits_valid_and_invalid_tokens() {
+        let (asns, invalid) =
+            parse_asns("13335, AS15169,asn64496,As7018,foo,,AS,4294967296, 400x ,-1");
+        assert_eq!(asns, vec![13335, 15169, 64496, 7018]);
+        assert_eq!(invalid, vec!["foo", "AS", "4294967296", "400x", "-1"]);
+
+        assert_eq!(parse_asns(""), (vec![], vec![]));
+        assert_eq!(parse_asns(" , "), (vec![], vec![]));
+    }
+
+    fn forwarded_request(peer: Option<&str>, forwarded_for: &str) -> AxumRequest {
+        let mut req = AxumRequest::builder()
+            .header("x-forwarded-for", forwarded_for)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        if let Some(peer) = peer {
+            let addr: SocketAddr = peer.parse().unwrap();
+            req.extensions_mut().insert(ConnectInfo(addr));
         }
+        req
     }
 
-    Ok(Json(json!(found)))
+    #[test]
+    fn client_ip_uses_rightmost_forwarded_entry() {
+        let req = forwarded_request(Some("127.0.0.1:4000"), "203.0.113.9, 198.51.100.7");
+        assert_eq!(client_ip(&req), Some("198.51.100.7".parse().unwrap()));
+
+        // unix socket peers count as a local proxy
+        let req = forwarded_request(None, "203.0.113.9,198.51.100.7");
+        assert_eq!(client_ip(&req), Some("198.51.100.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_header_from_public_peer() {
+        let req = forwarded_request(Some("192.0.2.1:4000"), "198.51.100.7");
+        assert_eq!(client_ip(&req), Some("192.0.2.1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn reload_annotations_swaps_in_annotated_records() {
+        for compressed in [false, true] {
+            let state = test_state(vec![record(13335, "CLOUDFLARENET", "US", None)], 10, 10);
+            let records: HashMap<u32, AsInfoOut> =
+                HashMap::from([(13335, record(13335, "CLOUDFLARENET", "US", None))]);
+            *state.etag.lock().unwrap() = dataset_etag(&records);
+            *state.map.lock().unwrap() = RecordMap::new(records, compressed);
+            let snapshot = state.map.lock().unwrap().clone();
+            let old_etag = state.etag.lock().unwrap().clone();
+
+            let notes = Annotations::from([(13335, "CDN".to_string())]);
+            assert!(crate::annotations::reload_annotations(&state, notes.clone()).await);
+
+            let served = state.map.lock().unwrap().get(&13335).unwrap();
+            assert_eq!(served.note.as_deref(), Some("CDN"));
+            assert_eq!(*state.annotations.lock().unwrap(), notes);
+            assert_ne!(*state.etag.lock().unwrap(), old_etag);
+            // snapshots taken before the reload keep serving the old records
+            assert_eq!(snapshot.get(&13335).unwrap().note, None);
+        }
+    }
+
+    #[test]
+    fn refreshed_data_replaces_state_and_keeps_changed_records() {
+        let state = test_state(
+            vec![
+                record(13335, "CLOUDFLARENET", "US", Some("Cloudflare, Inc.")),
+                record(15169, "GOOGLE", "US", Some("Google LLC")),
+            ],
+            10,
+            10,
+        );
+        let old_etag = state.etag.lock().unwrap().clone();
+        let current = state.map.lock().unwrap().clone();
+        let loaded: HashMap<u32, AsInfoOut> = [
+            record(13335, "CLOUDFLARENET", "US", Some("Cloudflare, Inc.")),
+            record(15169, "GOOGLE", "IE", Some("Google LLC")),
+            record(64512, "LAB", "NL", Some("Lab")),
+        ]
+        .into_iter()
+        .map(|r| (r.inner.asn, r))
+        .collect();
+        let notes = Annotations::from([(64512, "lab ASN".to_string())]);
+        let opts = LoadOptions {
+            compress_records: true,
+            ..Default::default()
+        };
+
+        let refreshed = Refreshed::build(
+            (loaded, "2026-02-01T00:00:00.000Z".to_string(), vec![]),
+            &notes,
+            &current,
+            &opts,
+        );
+        refreshed.swap_into(&state);
+
+        let map = state.map.lock().unwrap().clone();
+        assert_eq!(map.record_count(), 3);
+        assert_eq!(map.get(&64512).unwrap().note.as_deref(), Some("lab ASN"));
+        let previous = state.previous.lock().unwrap();
+        assert_eq!(previous.keys().collect::<Vec<_>>(), vec![&15169]);
+        assert_eq!(previous[&15169].inner.country, "US");
+        assert_ne!(*state.etag.lock().unwrap(), old_etag);
+        assert_eq!(state.org_index.lock().unwrap()["lab"], vec![64512]);
+        assert_eq!(
+            *state.updated_at.lock().unwrap(),
+            "2026-02-01T00:00:00.000Z"
+        );
+        // the snapshot taken before the swap is unaffected
+        assert_eq!(current.record_count(), 2);
+    }
 }
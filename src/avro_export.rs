@@ -0,0 +1,177 @@
+//! Avro export with the simplified (CSV) columns.
+//!
+//! The records are written as an Avro Object Container File with the writer schema embedded in
+//! the header, so readers need no separate schema. Like Parquet, the file is written directly and
+//! compressed internally (deflate).
+
+use crate::AsInfoSimplified;
+use apache_avro::types::Record;
+use apache_avro::{Codec, Schema, Writer};
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::BufWriter;
+use tracing::{error, info};
+
+/// Avro schema of the simplified record; `country_name` is left out with `compact_country`.
+///
+/// `asn` is a `long`, since Avro has no unsigned 32-bit type; the optional text columns are
+/// `["null", "string"]` unions defaulting to null.
+fn schema(compact_country: bool) -> Value {
+    let optional = |name: &str| json!({"name": name, "type": ["null", "string"], "default": null});
+    let mut fields = vec![
+        json!({"name": "asn", "type": "long"}),
+        json!({"name": "as_name", "type": "string"}),
+        optional("org_id"),
+        optional("org_name"),
+        json!({"name": "country_code", "type": "string"}),
+    ];
+    if !compact_country {
+        fields.push(optional("country_name"));
+    }
+    fields.push(json!({"name": "data_source", "type": "string"}));
+    json!({
+        "type": "record",
+        "name": "AsInfo",
+        "namespace": "com.bgpkit.asninfo",
+        "fields": fields,
+    })
+}
+
+/// Write `rows` to `path` as a deflate-compressed Avro Object Container File.
+pub fn write_avro(path: &str, rows: &[AsInfoSimplified], compact_country: bool) -> Result<(), i32> {
+    let schema = Schema::parse(&schema(compact_country)).map_err(|e| {
+        error!("failed to build avro schema: {e}");
+        1
+    })?;
+    let file = File::create(path).map_err(|e| {
+        error!("failed to create avro file '{path}': {e}");
+        1
+    })?;
+    let mut writer = Writer::with_codec(&schema, BufWriter::new(file), Codec::Deflate);
+    for row in rows {
+        let Some(mut record) = Record::new(writer.schema()) else {
+            error!("avro schema is not a record schema");
+            return Err(1);
+        };
+        record.put("asn", row.asn as i64);
+        record.put("as_name", row.as_name.as_str());
+        record.put("org_id", row.org_id.as_deref());
+        record.put("org_name", row.org_name.as_deref());
+        record.put("country_code", row.country_code.as_str());
+        if !compact_country {
+            record.put("country_name", row.country_name.as_deref());
+        }
+        record.put("data_source", row.data_source.as_str());
+        writer.append(record).map_err(|e| {
+            error!("failed to write avro record for AS{}: {e}", row.asn);
+            1
+        })?;
+    }
+    writer.flush().map_err(|e| {
+        error!("failed to finish avro file '{path}': {e}");
+        1
+    })?;
+    info!("wrote {} records to avro file '{path}'", rows.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apache_avro::types::Value as AvroValue;
+    use apache_avro::Reader;
+
+    fn row(asn: u32, org: Option<(&str, &str)>) -> AsInfoSimplified {
+        AsInfoSimplified {
+            asn,
+            as_name: format!("AS{asn}-NAME"),
+            org_id: org.map(|(id, _)| id.to_string()),
+            org_name: org.map(|(_, name)| name.to_string()),
+            country_code: "US".to_string(),
+            country_name: Some("United States".to_string()),
+            data_source: "ripe".to_string(),
+            note: None,
+            org_country: None,
+            as2org_name: None,
+        }
+    }
+
+    /// Write `rows` and read them back with the schema embedded in the file.
+    fn round_trip(rows: &[AsInfoSimplified], compact_country: bool) -> (Schema, Vec<AvroValue>) {
+        let path = std::env::temp_dir().join(format!(
+            "asninfo-avro-{}-{compact_country}.avro",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        write_avro(path, rows, compact_country).unwrap();
+        let reader = Reader::new(File::open(path).unwrap()).unwrap();
+        let schema = reader.writer_schema().clone();
+        let records = reader.map(Result::unwrap).collect();
+        std::fs::remove_file(path).unwrap();
+        (schema, records)
+    }
+
+    fn field<'a>(record: &'a AvroValue, name: &str) -> &'a AvroValue {
+        let AvroValue::Record(fields) = record else {
+            panic!("not a record: {record:?}");
+        };
+        &fields.iter().find(|(n, _)| n == name).unwrap().1
+    }
+
+    #[test]
+    fn records_round_trip_with_the_embedded_schema() {
+        let rows = [
+            row(64496, Some(("ORG-1", "Example"))),
+            row(4294967295, None),
+        ];
+        let (schema, records) = round_trip(&rows, false);
+        assert_eq!(schema, Schema::parse(&super::schema(false)).unwrap());
+        assert_eq!(
+            schema.canonical_form(),
+            "{\"name\":\"com.bgpkit.asninfo.AsInfo\",\"type\":\"record\",\"fields\":[\
+             {\"name\":\"asn\",\"type\":\"long\"},{\"name\":\"as_name\",\"type\":\"string\"},\
+             {\"name\":\"org_id\",\"type\":[\"null\",\"string\"]},\
+             {\"name\":\"org_name\",\"type\":[\"null\",\"string\"]},\
+             {\"name\":\"country_code\",\"type\":\"string\"},\
+             {\"name\":\"country_name\",\"type\":[\"null\",\"string\"]},\
+             {\"name\":\"data_source\",\"type\":\"string\"}]}"
+        );
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(field(&records[0], "asn"), &AvroValue::Long(64496));
+        assert_eq!(
+            field(&records[0], "org_name"),
+            &AvroValue::Union(1, Box::new(AvroValue::String("Example".to_string())))
+        );
+        assert_eq!(field(&records[1], "asn"), &AvroValue::Long(4294967295));
+        assert_eq!(
+            field(&records[1], "org_id"),
+            &AvroValue::Union(0, Box::new(AvroValue::Null))
+        );
+        assert_eq!(
+            field(&records[1], "country_name"),
+            &AvroValue::Union(1, Box::new(AvroValue::String("United States".to_string())))
+        );
+    }
+
+    #[test]
+    fn compact_country_drops_the_country_name_column() {
+        let (schema, records) = round_trip(&[row(64496, None)], true);
+        assert!(!schema.canonical_form().contains("country_name"));
+        let AvroValue::Record(fields) = &records[0] else {
+            panic!("not a record");
+        };
+        let names: Vec<&str> = fields.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "asn",
+                "as_name",
+                "org_id",
+                "org_name",
+                "country_code",
+                "data_source"
+            ]
+        );
+    }
+}
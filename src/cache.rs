@@ -0,0 +1,215 @@
+//! On-disk cache of the enriched records served by `serve`, given with `--cache-dir`.
+//!
+//! After every complete load the records are written to `asninfo-serve-cache.jsonl.zst` in the
+//! cache directory: a header line with the load timestamp and the load options, then one
+//! `AsInfoOut` per line. On startup a cache younger than the refresh interval and written with
+//! the same options is served right away instead of downloading the datasets again.
+
+use crate::api::{AsInfoOut, LoadOptions, LoadedRecords};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+const CACHE_FILE_NAME: &str = "asninfo-serve-cache.jsonl.zst";
+/// Written first and renamed into place, so a crash never leaves a truncated cache behind.
+const PARTIAL_CACHE_FILE_NAME: &str = "asninfo-serve-cache.partial.jsonl.zst";
+
+#[derive(Serialize, Deserialize)]
+struct CacheHeader {
+    updated_at: String,
+    /// Load options the records were built with, see [`options_key`]
+    options: String,
+}
+
+/// The load options that change the shape of the records; a cache written with different
+/// options is ignored.
+fn options_key(opts: &LoadOptions) -> String {
+    serde_json::json!({
+        "simplified": opts.simplified,
+        "with_geo": opts.with_geo,
+        "with_ix": opts.with_ix,
+        "compact_country": opts.compact_country,
+        "with_continent": opts.with_continent,
+        "manifest": opts.manifest,
+    })
+    .to_string()
+}
+
+/// Load the cached records if the cache is younger than `max_age` and matches `opts`.
+///
+/// Returns the records and the age of the cache. Missing, stale, or unreadable caches are logged
+/// and skipped, since the records can always be loaded from upstream instead.
+pub fn load(dir: &str, opts: &LoadOptions, max_age: Duration) -> Option<(LoadedRecords, Duration)> {
+    let path = Path::new(dir).join(CACHE_FILE_NAME);
+    let age = std::fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .ok()
+        .map(|modified| {
+            SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default()
+        })?;
+    if age >= max_age {
+        info!(
+            "serve cache '{}' is {}s old, loading fresh data",
+            path.display(),
+            age.as_secs()
+        );
+        return None;
+    }
+    match read_cache(&path, opts) {
+        Ok(records) => {
+            info!(
+                "loaded {} records from serve cache '{}' ({}s old)",
+                records.0.len(),
+                path.display(),
+                age.as_secs()
+            );
+            Some((records, age))
+        }
+        Err(reason) => {
+            warn!("ignoring serve cache '{}': {reason}", path.display());
+            None
+        }
+    }
+}
+
+fn read_cache(path: &Path, opts: &LoadOptions) -> Result<LoadedRecords, String> {
+    let reader = oneio::get_reader(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+    let mut lines = BufReader::new(reader).lines();
+    let header: CacheHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line.map_err(|e| e.to_string())?)
+            .map_err(|e| format!("invalid header: {e}"))?,
+        None => return Err("empty file".to_string()),
+    };
+    if header.options != options_key(opts) {
+        return Err("written with different load options".to_string());
+    }
+    let mut map = HashMap::new();
+    for line in lines {
+        let line = line.map_err(|e| e.to_string())?;
+        let record: AsInfoOut =
+            serde_json::from_str(&line).map_err(|e| format!("invalid record: {e}"))?;
+        map.insert(record.inner.asn, record);
+    }
+    Ok((map, header.updated_at, Vec::new()))
+}
+
+/// Write the records to the cache; failures are logged, the cache is only an optimization.
+pub fn store(dir: &str, opts: &LoadOptions, map: &HashMap<u32, AsInfoOut>, updated_at: &str) {
+    let dir = PathBuf::from(dir);
+    let partial = dir.join(PARTIAL_CACHE_FILE_NAME);
+    let path = dir.join(CACHE_FILE_NAME);
+    let result = std::fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|_| write_cache(&partial, opts, map, updated_at))
+        .and_then(|_| std::fs::rename(&partial, &path).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => info!(
+            "wrote {} records to serve cache '{}'",
+            map.len(),
+            path.display()
+        ),
+        Err(e) => warn!("failed to write serve cache '{}': {e}", path.display()),
+    }
+}
+
+fn write_cache(
+    path: &Path,
+    opts: &LoadOptions,
+    map: &HashMap<u32, AsInfoOut>,
+    updated_at: &str,
+) -> Result<(), String> {
+    let mut writer = oneio::get_writer(&path.to_string_lossy()).map_err(|e| e.to_string())?;
+    let header = CacheHeader {
+        updated_at: updated_at.to_string(),
+        options: options_key(opts),
+    };
+    let header = serde_json::to_string(&header).map_err(|e| e.to_string())?;
+    writeln!(writer, "{header}").map_err(|e| e.to_string())?;
+    for record in map.values() {
+        let line = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        writeln!(writer, "{line}").map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::data_source;
+    use bgpkit_commons::asinfo::AsInfo;
+
+    fn record(asn: u32) -> AsInfoOut {
+        let inner = AsInfo {
+            asn,
+            name: format!("AS{asn}-NAME"),
+            country: "US".to_string(),
+            as2org: None,
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        };
+        AsInfoOut {
+            data_source: data_source(&inner),
+            inner,
+            country_name: Some("United States".to_string()),
+            continent: None,
+            countries: None,
+            ix_count: Some(0),
+            ixs: Some(Vec::new()),
+            note: None,
+        }
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("asninfo-cache-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn stored_records_load_back() {
+        let dir = cache_dir("round-trip");
+        let dir_str = dir.to_str().unwrap();
+        let opts = LoadOptions {
+            with_ix: true,
+            ..Default::default()
+        };
+        let map: HashMap<u32, AsInfoOut> = [(64496, record(64496)), (64497, record(64497))].into();
+        store(dir_str, &opts, &map, "2026-01-01T00:00:00.000Z");
+        assert!(!dir.join(PARTIAL_CACHE_FILE_NAME).exists());
+
+        let ((loaded, updated_at, failed), _age) =
+            load(dir_str, &opts, Duration::from_secs(3600)).unwrap();
+        assert_eq!(updated_at, "2026-01-01T00:00:00.000Z");
+        assert!(failed.is_empty());
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&loaded[&64496]).unwrap(),
+            serde_json::to_value(&map[&64496]).unwrap()
+        );
+
+        // a cache is only used with the options it was written with, and while it is fresh
+        let other = LoadOptions {
+            with_ix: false,
+            ..Default::default()
+        };
+        assert!(load(dir_str, &other, Duration::from_secs(3600)).is_none());
+        assert!(load(dir_str, &opts, Duration::ZERO).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_cache_is_skipped() {
+        let dir = cache_dir("missing");
+        assert!(load(
+            dir.to_str().unwrap(),
+            &LoadOptions::default(),
+            Duration::MAX
+        )
+        .is_none());
+    }
+}
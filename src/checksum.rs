@@ -0,0 +1,81 @@
+//! SHA-256 checksum sidecars for generated files.
+//!
+//! The checksum is written next to the data file as `<path>.sha256` in the `sha256sum` format
+//! (`<hex digest>  <file name>`), so downloads can be checked with `sha256sum -c`. The copy
+//! uploaded next to an S3/R2 object names the object key's basename instead of the local file.
+
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+/// Hex-encoded SHA-256 digest of the file at `path`.
+pub fn sha256_file(path: &str) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hash the file at `path` and write the digest to `<path>.sha256`, naming the local file.
+///
+/// Returns the checksum file path and the hex digest.
+pub fn write_checksum(path: &str) -> Result<(String, String), i32> {
+    let digest = sha256_file(path).map_err(|e| {
+        error!("failed to hash '{path}': {e}");
+        1
+    })?;
+    let checksum_path = format!("{path}.sha256");
+    write_checksum_line(&checksum_path, &digest, base_name(path))?;
+    info!("wrote sha256 checksum of '{path}' to '{checksum_path}'");
+    Ok((checksum_path, digest))
+}
+
+/// Write the sidecar to upload next to the object at `key` to a temporary file, naming the key's
+/// basename so `sha256sum -c` works on the downloaded object whatever the local file was called.
+///
+/// Returns the temporary file path; the caller removes it after uploading.
+pub fn write_upload_checksum(digest: &str, key: &str) -> Result<String, i32> {
+    let file_name = base_name(key);
+    let checksum_path = std::env::temp_dir()
+        .join(format!("asninfo-{}-{file_name}.sha256", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    write_checksum_line(&checksum_path, digest, file_name)?;
+    Ok(checksum_path)
+}
+
+/// Last `/`-separated component of a path or object key.
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn write_checksum_line(checksum_path: &str, digest: &str, file_name: &str) -> Result<(), i32> {
+    std::fs::write(checksum_path, format!("{digest}  {file_name}\n")).map_err(|e| {
+        error!("failed to write checksum file '{checksum_path}': {e}");
+        1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_checksum_names_key_basename() {
+        let dir = std::env::temp_dir().join(format!("asninfo-checksum-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("local-2024.json");
+        std::fs::write(&path, "[]\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let (local_sidecar, digest) = write_checksum(path).unwrap();
+        let local = std::fs::read_to_string(&local_sidecar).unwrap();
+        assert_eq!(local, format!("{digest}  local-2024.json\n"));
+
+        let upload_sidecar = write_upload_checksum(&digest, "exports/asninfo/latest.json").unwrap();
+        let uploaded = std::fs::read_to_string(&upload_sidecar).unwrap();
+        assert_eq!(uploaded, format!("{digest}  latest.json\n"));
+
+        std::fs::remove_file(upload_sidecar).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
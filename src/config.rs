@@ -0,0 +1,114 @@
+//! Optional TOML configuration file, given with `--config`.
+//!
+//! The file collects settings that are otherwise spread over flags and environment variables.
+//! Command-line flags take precedence over environment variables, which take precedence over the
+//! file. Credentials such as the AWS keys are deliberately not read from it; keep them in the
+//! environment or a `.env` file.
+//!
+//! ```toml
+//! bind = "0.0.0.0:8080"
+//! refresh_secs = 21600
+//! max_asns = 500
+//! max_asns_ceiling = 5000
+//! upload_paths = ["r2://primary/asninfo.jsonl", "s3://backup/asninfo.jsonl"]
+//! heartbeat_url = "https://example.com/heartbeat"
+//!
+//! [datasets]
+//! population = false
+//! hegemony = false
+//! ```
+
+use crate::dataset::DatasetManifest;
+use serde::Deserialize;
+use tracing::{error, info};
+
+/// Bind address of `serve` when neither `--bind` nor the config file sets one.
+pub const DEFAULT_BIND: &str = "0.0.0.0:8080";
+
+/// Interval between refreshes (`serve`) or runs (`daemon`) unless configured.
+pub const DEFAULT_REFRESH_SECS: u64 = 21600;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `serve` bind address, e.g. `0.0.0.0:8080` or `unix:/run/asninfo.sock`
+    pub bind: Option<String>,
+    /// `serve` refresh interval and `daemon` run interval in seconds
+    pub refresh_secs: Option<u64>,
+    /// Maximum ASNs per lookup request (`ASNINFO_MAX_ASNS`)
+    pub max_asns: Option<usize>,
+    /// Highest per-request `limit` a lookup may ask for (`ASNINFO_MAX_ASNS_CEILING`)
+    pub max_asns_ceiling: Option<usize>,
+    /// Upload destinations (`ASNINFO_UPLOAD_PATH`)
+    pub upload_paths: Vec<String>,
+    /// Heartbeat URL requested after a successful upload (`ASNINFO_HEARTBEAT_URL`)
+    pub heartbeat_url: Option<String>,
+    /// Dataset toggles, applied on top of the dataset manifest
+    pub datasets: DatasetToggles,
+}
+
+/// Turn individual datasets on or off; unset entries keep the manifest's setting.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatasetToggles {
+    pub as2org: Option<bool>,
+    pub population: Option<bool>,
+    pub hegemony: Option<bool>,
+    pub peeringdb: Option<bool>,
+    pub countries: Option<bool>,
+}
+
+impl Config {
+    /// Load the configuration from a local or remote TOML file.
+    pub fn load(path: &str) -> Result<Self, i32> {
+        let content = oneio::read_to_string(path).map_err(|e| {
+            error!("failed to read config file '{path}': {e}");
+            1
+        })?;
+        let config: Config = toml::from_str(&content).map_err(|e| {
+            error!("failed to parse config file '{path}': {e}");
+            1
+        })?;
+        info!("loaded config file '{path}'");
+        Ok(config)
+    }
+
+    /// Export the settings read from environment variables, unless the variable is already set.
+    ///
+    /// Must run before any other thread reads the environment.
+    pub fn apply_env(&self) {
+        let upload_paths = (!self.upload_paths.is_empty()).then(|| self.upload_paths.join(","));
+        let vars = [
+            ("ASNINFO_MAX_ASNS", self.max_asns.map(|n| n.to_string())),
+            (
+                "ASNINFO_MAX_ASNS_CEILING",
+                self.max_asns_ceiling.map(|n| n.to_string()),
+            ),
+            ("ASNINFO_UPLOAD_PATH", upload_paths),
+            ("ASNINFO_HEARTBEAT_URL", self.heartbeat_url.clone()),
+        ];
+        for (key, value) in vars {
+            if let Some(value) = value {
+                if std::env::var_os(key).is_none() {
+                    std::env::set_var(key, value);
+                }
+            }
+        }
+    }
+
+    /// Apply the dataset toggles to `manifest`.
+    pub fn apply_datasets(&self, manifest: &mut DatasetManifest) {
+        let toggles = [
+            (&mut manifest.as2org, self.datasets.as2org),
+            (&mut manifest.population, self.datasets.population),
+            (&mut manifest.hegemony, self.datasets.hegemony),
+            (&mut manifest.peeringdb, self.datasets.peeringdb),
+            (&mut manifest.countries, self.datasets.countries),
+        ];
+        for (source, enabled) in toggles {
+            if let Some(enabled) = enabled {
+                source.enabled = enabled;
+            }
+        }
+    }
+}
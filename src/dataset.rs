@@ -0,0 +1,422 @@
+//! Dataset source manifest and loading.
+//!
+//! A manifest is a JSON file listing each dataset with an `enabled` flag and an optional
+//! `cache_ttl_secs`, e.g.:
+//!
+//! ```json
+//! {
+//!   "as2org": { "enabled": true },
+//!   "peeringdb": { "enabled": false },
+//!   "hegemony": { "cache_ttl_secs": 3600 }
+//! }
+//! ```
+//!
+//! Datasets missing from the manifest are enabled with upstream defaults. Sources are always the
+//! bgpkit-commons defaults, so unknown fields such as `url` are rejected.
+
+use crate::ExportFormat;
+use bgpkit_commons::asinfo::{As2orgInfo, AsInfo};
+use bgpkit_commons::BgpkitCommons;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatasetSource {
+    /// Whether this dataset is loaded at all
+    pub enabled: bool,
+    /// How long loaded data is considered fresh, in seconds
+    pub cache_ttl_secs: Option<u64>,
+}
+
+impl Default for DatasetSource {
+    fn default() -> Self {
+        DatasetSource {
+            enabled: true,
+            cache_ttl_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatasetManifest {
+    pub as2org: DatasetSource,
+    pub population: DatasetSource,
+    pub hegemony: DatasetSource,
+    pub peeringdb: DatasetSource,
+    pub countries: DatasetSource,
+}
+
+impl DatasetManifest {
+    /// Load a manifest from a local or remote JSON file.
+    pub fn from_path(path: &str) -> Result<Self, i32> {
+        let content = oneio::read_to_string(path).map_err(|e| {
+            error!("failed to read dataset manifest '{path}': {e}");
+            1
+        })?;
+        let manifest: DatasetManifest = serde_json::from_str(&content).map_err(|e| {
+            error!("failed to parse dataset manifest '{path}': {e}");
+            1
+        })?;
+        info!("loaded dataset manifest from '{}'", path);
+        Ok(manifest)
+    }
+
+    fn sources(&self) -> [(&'static str, &DatasetSource); 5] {
+        [
+            ("as2org", &self.as2org),
+            ("population", &self.population),
+            ("hegemony", &self.hegemony),
+            ("peeringdb", &self.peeringdb),
+            ("countries", &self.countries),
+        ]
+    }
+
+    /// Shortest cache TTL across enabled datasets, if any is set.
+    pub fn min_cache_ttl_secs(&self) -> Option<u64> {
+        self.sources()
+            .iter()
+            .filter(|(_, s)| s.enabled)
+            .filter_map(|(_, s)| s.cache_ttl_secs)
+            .min()
+    }
+}
+
+/// Default number of retries of a failed dataset load.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Default delay before the first retry of a failed dataset load, in seconds.
+pub const DEFAULT_RETRY_DELAY_SECS: u64 = 10;
+
+/// Retries of a failed upstream dataset load, with exponential backoff.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadRetry {
+    /// Retries after the first failed attempt; 0 fails right away
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after each further failure
+    pub base_delay: Duration,
+}
+
+impl Default for LoadRetry {
+    fn default() -> Self {
+        LoadRetry {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: Duration::from_secs(DEFAULT_RETRY_DELAY_SECS),
+        }
+    }
+}
+
+impl LoadRetry {
+    /// Delay before retry number `retry` (0-based).
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << retry.min(16))
+    }
+}
+
+/// Country lookups and the ASN info records, as returned by [`load_commons`].
+pub type LoadedCommons = (BgpkitCommons, HashMap<u32, AsInfo>);
+
+/// Load the ASN info and country datasets selected by `manifest`.
+///
+/// `simplified` additionally skips the heavy datasets (population, hegemony, PeeringDB). A failed
+/// ASN info load is retried per `retry`, sleeping on the calling thread between attempts.
+///
+/// The countries download runs alongside the ASN info load on its own `BgpkitCommons`, which is
+/// returned for country lookups together with the ASN info records.
+pub fn load_commons(
+    manifest: &DatasetManifest,
+    simplified: bool,
+    retry: &LoadRetry,
+) -> Result<LoadedCommons, i32> {
+    let load_as2org = manifest.as2org.enabled;
+    let load_population = !simplified && manifest.population.enabled;
+    let load_hegemony = !simplified && manifest.hegemony.enabled;
+    let load_peeringdb = !simplified && manifest.peeringdb.enabled;
+
+    let load_asinfo = || -> Result<HashMap<u32, AsInfo>, i32> {
+        info!("loading asn info data ...");
+        let mut commons = BgpkitCommons::new();
+        let attempts = retry.max_retries + 1;
+        for attempt in 1..=attempts {
+            match commons.load_asinfo(load_as2org, load_population, load_hegemony, load_peeringdb) {
+                Ok(_) => break,
+                Err(e) if attempt < attempts => {
+                    let delay = retry.delay(attempt - 1);
+                    warn!(
+                        "failed to load asn info data (attempt {attempt} of {attempts}), retrying in {}s: {e}",
+                        delay.as_secs()
+                    );
+                    std::thread::sleep(delay);
+                }
+                Err(e) => {
+                    error!("failed to load asn info data after {attempts} attempts: {e}");
+                    return Err(1);
+                }
+            }
+        }
+        commons.asinfo_all().map_err(|e| {
+            error!("failed to get asinfo map: {e}");
+            3
+        })
+    };
+    // country names are only enrichment; keep going with empty names if they fail to load
+    let load_countries = || -> Result<BgpkitCommons, i32> {
+        let mut countries = BgpkitCommons::new();
+        if let Err(e) = countries.load_countries() {
+            warn!("failed to load countries, country names will be empty: {e}");
+        }
+        Ok(countries)
+    };
+    let (as_info_map, countries) = load_concurrently(
+        load_asinfo,
+        manifest.countries.enabled.then_some(load_countries),
+    )?;
+    Ok((countries.unwrap_or_default(), as_info_map))
+}
+
+/// Run `side` on a scoped thread while `main` runs on the current one, so independent downloads
+/// overlap instead of adding up.
+///
+/// Both loads run to completion; `main`'s error takes precedence, so a failure maps to the same
+/// exit code as when the loads ran one after the other.
+pub fn load_concurrently<A, B: Send>(
+    main: impl FnOnce() -> Result<A, i32>,
+    side: Option<impl FnOnce() -> Result<B, i32> + Send>,
+) -> Result<(A, Option<B>), i32> {
+    std::thread::scope(|scope| {
+        let handle = side.map(|side| scope.spawn(side));
+        let main = main();
+        let side = handle
+            .map(|h| {
+                h.join().unwrap_or_else(|_| {
+                    error!("dataset loading thread panicked");
+                    Err(1)
+                })
+            })
+            .transpose();
+        Ok((main?, side?))
+    })
+}
+
+/// Like [`load_commons`], but if the full load fails, fall back to loading without the optional
+/// datasets instead of failing: first without population, hegemony, and PeeringDB, then also
+/// without as2org. Only the RIPE AS names are required.
+///
+/// Returns the loaded data and the names of the requested datasets that were given up.
+pub fn load_commons_partial(
+    manifest: &DatasetManifest,
+    simplified: bool,
+    retry: &LoadRetry,
+) -> Result<(LoadedCommons, Vec<&'static str>), i32> {
+    let mut fallback = manifest.clone();
+    let mut failed = Vec::new();
+    let mut result = load_commons(&fallback, simplified, retry);
+    // give up the heavy datasets first (not loaded in simplified mode anyway), then as2org
+    for stage in 0..2 {
+        if result.is_ok() {
+            break;
+        }
+        let sources: Vec<(&'static str, &mut DatasetSource)> = match stage {
+            0 if simplified => Vec::new(),
+            0 => vec![
+                ("population", &mut fallback.population),
+                ("hegemony", &mut fallback.hegemony),
+                ("peeringdb", &mut fallback.peeringdb),
+            ],
+            _ => vec![("caida", &mut fallback.as2org)],
+        };
+        let dropped: Vec<&'static str> = sources
+            .into_iter()
+            .filter(|(_, source)| source.enabled)
+            .map(|(name, source)| {
+                source.enabled = false;
+                name
+            })
+            .collect();
+        if dropped.is_empty() {
+            continue;
+        }
+        warn!("retrying without optional datasets: {}", dropped.join(", "));
+        failed.extend(dropped);
+        result = load_commons(&fallback, simplified, retry);
+    }
+    let loaded = result?;
+    if !failed.is_empty() {
+        warn!(
+            "started with a partial dataset; not loaded: {}",
+            failed.join(", ")
+        );
+    }
+    Ok((loaded, failed))
+}
+
+/// Load records from a previously generated full-schema JSON or JSONL dump, or a CSV export,
+/// instead of the upstream sources, e.g. to run offline from a known-good snapshot.
+///
+/// Extra fields such as `ix_count` or `note` are ignored; simplified and legacy JSON dumps lack
+/// the full record and are rejected. CSV rows only carry the simplified columns, see
+/// [`CsvSnapshotRecord`].
+pub fn load_snapshot(path: &str) -> Result<HashMap<u32, AsInfo>, i32> {
+    info!("loading asn info data from snapshot '{path}' ...");
+    let records: Vec<AsInfo> = match ExportFormat::from_path(path) {
+        Some(ExportFormat::JSONL) => {
+            let reader = oneio::get_reader(path).map_err(|e| {
+                error!("failed to open snapshot '{path}': {e}");
+                1
+            })?;
+            let mut records = Vec::new();
+            for (i, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line.map_err(|e| {
+                    error!("failed to read snapshot '{path}': {e}");
+                    1
+                })?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let record = serde_json::from_str(&line).map_err(|e| {
+                    error!("invalid record on line {} of snapshot '{path}': {e}", i + 1);
+                    1
+                })?;
+                records.push(record);
+            }
+            records
+        }
+        Some(ExportFormat::JSON) => {
+            let content = oneio::read_to_string(path).map_err(|e| {
+                error!("failed to read snapshot '{path}': {e}");
+                1
+            })?;
+            serde_json::from_str(&content).map_err(|e| {
+                error!("invalid records in snapshot '{path}': {e}");
+                1
+            })?
+        }
+        Some(ExportFormat::CSV) => load_csv_snapshot(path)?,
+        _ => {
+            error!(
+                "snapshot '{path}' must be a full-schema .json or .jsonl dump, or a .csv export"
+            );
+            return Err(1);
+        }
+    };
+    info!("loaded {} records from snapshot '{path}'", records.len());
+    Ok(records.into_iter().map(|v| (v.asn, v)).collect())
+}
+
+/// A row of a CSV export as read back by [`load_snapshot`].
+///
+/// Only `asn` is required, so exports written with `--columns` load as long as they keep it.
+/// `country_name` and `data_source` are derived again on load and ignored here.
+#[derive(Deserialize)]
+struct CsvSnapshotRecord {
+    asn: u32,
+    #[serde(default)]
+    as_name: String,
+    #[serde(default)]
+    org_id: Option<String>,
+    #[serde(default)]
+    org_name: Option<String>,
+    #[serde(default)]
+    country_code: String,
+    /// Only present in `--full-org` exports
+    #[serde(default)]
+    org_country: Option<String>,
+    /// Only present in `--full-org` exports
+    #[serde(default)]
+    as2org_name: Option<String>,
+}
+
+impl From<CsvSnapshotRecord> for AsInfo {
+    /// Rebuild a record with the as2org fields the CSV carries; population, hegemony, and
+    /// PeeringDB data are not part of the CSV export and stay empty.
+    fn from(row: CsvSnapshotRecord) -> Self {
+        let as2org = (row.org_id.is_some() || row.org_name.is_some()).then(|| As2orgInfo {
+            name: row.as2org_name.unwrap_or_else(|| row.as_name.clone()),
+            country: row.org_country.unwrap_or_else(|| row.country_code.clone()),
+            org_id: row.org_id.unwrap_or_default(),
+            org_name: row.org_name.unwrap_or_default(),
+        });
+        AsInfo {
+            asn: row.asn,
+            name: row.as_name,
+            country: row.country_code,
+            as2org,
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        }
+    }
+}
+
+fn load_csv_snapshot(path: &str) -> Result<Vec<AsInfo>, i32> {
+    let reader = oneio::get_reader(path).map_err(|e| {
+        error!("failed to open snapshot '{path}': {e}");
+        1
+    })?;
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let mut records = Vec::new();
+    for (i, row) in csv_reader.deserialize::<CsvSnapshotRecord>().enumerate() {
+        // line 1 is the header
+        let row = row.map_err(|e| {
+            error!("invalid record on line {} of snapshot '{path}': {e}", i + 2);
+            1
+        })?;
+        records.push(AsInfo::from(row));
+    }
+    Ok(records)
+}
+
+/// Datasets that were requested but contributed nothing, e.g. because a source failed to load
+/// and was skipped.
+pub fn skipped_datasets(
+    commons: &BgpkitCommons,
+    as_info_map: &HashMap<u32, AsInfo>,
+    manifest: &DatasetManifest,
+    simplified: bool,
+) -> Vec<&'static str> {
+    let none_have = |f: fn(&AsInfo) -> bool| !as_info_map.values().any(f);
+    let heavy = !simplified;
+    let mut skipped = Vec::new();
+    if manifest.as2org.enabled && none_have(|v| v.as2org.is_some()) {
+        skipped.push("as2org");
+    }
+    if heavy && manifest.population.enabled && none_have(|v| v.population.is_some()) {
+        skipped.push("population");
+    }
+    if heavy && manifest.hegemony.enabled && none_have(|v| v.hegemony.is_some()) {
+        skipped.push("hegemony");
+    }
+    if heavy && manifest.peeringdb.enabled && none_have(|v| v.peeringdb.is_some()) {
+        skipped.push("peeringdb");
+    }
+    // country lookups error out when the country dataset is not loaded
+    if manifest.countries.enabled && commons.country_by_code("US").is_err() {
+        skipped.push("countries");
+    }
+    skipped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_rejects_url_override() {
+        let manifest: DatasetManifest = serde_json::from_str(
+            r#"{"peeringdb":{"enabled":false},"hegemony":{"cache_ttl_secs":60}}"#,
+        )
+        .unwrap();
+        assert!(!manifest.peeringdb.enabled);
+        assert!(manifest.as2org.enabled);
+        assert_eq!(manifest.min_cache_ttl_secs(), Some(60));
+
+        let err = serde_json::from_str::<DatasetManifest>(
+            r#"{"as2org":{"url":"https://example.com/as2org.jsonl"}}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("unknown field `url`"), "{err}");
+    }
+}
@@ -0,0 +1,102 @@
+//! Compare two full-schema dumps and report added, removed, and changed ASNs.
+//!
+//! Only `name`, `country`, and the as2org `org_name` are compared; records that agree on all
+//! three are skipped. Changes are written to stdout as JSONL in ASN order, e.g.
+//! `{"asn":64512,"change_type":"changed","changes":{"name":{"old":"A","new":"B"}}}`.
+
+use crate::dataset::load_snapshot;
+use bgpkit_commons::asinfo::AsInfo;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+use tracing::error;
+
+/// Values of the compared fields, in output order.
+fn tracked_fields(info: &AsInfo) -> [(&'static str, Option<&str>); 3] {
+    [
+        ("name", Some(info.name.as_str())),
+        ("country", Some(info.country.as_str())),
+        (
+            "org_name",
+            info.as2org.as_ref().map(|v| v.org_name.as_str()),
+        ),
+    ]
+}
+
+fn record_fields(info: &AsInfo) -> Map<String, Value> {
+    tracked_fields(info)
+        .into_iter()
+        .map(|(field, value)| (field.to_string(), json!(value)))
+        .collect()
+}
+
+/// Print the differences between the `old` and `new` dumps, as JSONL or as a summary.
+pub fn diff_cmd(old: &str, new: &str, summary: bool) -> Result<(), i32> {
+    let old_map = load_snapshot(old)?;
+    let new_map = load_snapshot(new)?;
+    let asns: BTreeSet<u32> = old_map.keys().chain(new_map.keys()).copied().collect();
+
+    let mut added = 0;
+    let mut removed = 0;
+    let mut changed = 0;
+    let mut field_changes = [0usize; 3];
+    for asn in asns {
+        let mut change = Map::new();
+        change.insert("asn".to_string(), json!(asn));
+        match (old_map.get(&asn), new_map.get(&asn)) {
+            (None, Some(info)) => {
+                added += 1;
+                change.insert("change_type".to_string(), json!("added"));
+                change.extend(record_fields(info));
+            }
+            (Some(info), None) => {
+                removed += 1;
+                change.insert("change_type".to_string(), json!("removed"));
+                change.extend(record_fields(info));
+            }
+            (Some(old_info), Some(new_info)) => {
+                let mut changes = Map::new();
+                let fields = tracked_fields(old_info)
+                    .into_iter()
+                    .zip(tracked_fields(new_info));
+                for (i, ((field, old_value), (_, new_value))) in fields.enumerate() {
+                    if old_value != new_value {
+                        field_changes[i] += 1;
+                        changes.insert(
+                            field.to_string(),
+                            json!({"old": old_value, "new": new_value}),
+                        );
+                    }
+                }
+                if changes.is_empty() {
+                    continue;
+                }
+                changed += 1;
+                change.insert("change_type".to_string(), json!("changed"));
+                change.insert("changes".to_string(), Value::Object(changes));
+            }
+            (None, None) => continue,
+        }
+        if summary {
+            continue;
+        }
+        match serde_json::to_string(&change) {
+            Ok(line) => println!("{line}"),
+            Err(e) => {
+                error!("failed to serialize change for AS{asn}: {e}");
+                return Err(1);
+            }
+        }
+    }
+
+    if summary {
+        println!("old: {} records ({old})", old_map.len());
+        println!("new: {} records ({new})", new_map.len());
+        println!("added: {added}");
+        println!("removed: {removed}");
+        println!(
+            "changed: {changed} (name: {}, country: {}, org_name: {})",
+            field_changes[0], field_changes[1], field_changes[2]
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,471 @@
+//! Record filter expressions for `generate --filter`.
+//!
+//! An expression compares record fields against literals and combines the comparisons with
+//! boolean operators, e.g.:
+//!
+//! ```text
+//! country == "US" && (org_name contains "llc" || asn >= 64512)
+//! ```
+//!
+//! - Fields: `asn` (number); `name`, `country`, `org_id`, `org_name`, `org_country` (strings).
+//!   `as_name` and `country_code` are accepted as aliases of `name` and `country`.
+//! - String operators: `==`, `!=` (exact), and `contains`, `starts_with`, `ends_with`
+//!   (case-insensitive). Org fields are empty strings when the ASN has no as2org record.
+//! - Number operators: `==`, `!=`, `<`, `<=`, `>`, `>=`.
+//! - Boolean operators: `&&`, `||`, `!`, and parentheses; `&&` binds tighter than `||`.
+//! - String literals are double-quoted; a backslash escapes the next character, e.g. `"a \"b\""`.
+//!
+//! Parse errors name the 1-based column of the offending token.
+
+use bgpkit_commons::asinfo::AsInfo;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Asn,
+    Name,
+    Country,
+    OrgId,
+    OrgName,
+    OrgCountry,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "asn" => Some(Field::Asn),
+            "name" | "as_name" => Some(Field::Name),
+            "country" | "country_code" => Some(Field::Country),
+            "org_id" => Some(Field::OrgId),
+            "org_name" => Some(Field::OrgName),
+            "org_country" => Some(Field::OrgCountry),
+            _ => None,
+        }
+    }
+
+    fn str_value(self, info: &AsInfo) -> &str {
+        let as2org = info.as2org.as_ref();
+        match self {
+            Field::Name => &info.name,
+            Field::Country => &info.country,
+            Field::OrgId => as2org.map_or("", |v| v.org_id.as_str()),
+            Field::OrgName => as2org.map_or("", |v| v.org_name.as_str()),
+            Field::OrgCountry => as2org.map_or("", |v| v.country.as_str()),
+            Field::Asn => "",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Str(Field, Op, String),
+    /// Comparison of `asn` with a number
+    Num(Op, u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(u64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// Split `input` into tokens, each paired with its 1-based column for error messages.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().enumerate().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        let column = i + 1;
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push((column, Token::LParen));
+            }
+            ')' => {
+                chars.next();
+                tokens.push((column, Token::RParen));
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                let unterminated = || format!("unterminated string literal at column {column}");
+                loop {
+                    match chars.next().map(|(_, ch)| ch) {
+                        Some('\\') => match chars.next() {
+                            Some((_, escaped)) => s.push(escaped),
+                            None => return Err(unterminated()),
+                        },
+                        Some('"') => break,
+                        Some(ch) => s.push(ch),
+                        None => return Err(unterminated()),
+                    }
+                }
+                tokens.push((column, Token::Str(s)));
+            }
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(&(_, d)) = chars.peek().filter(|(_, d)| d.is_ascii_digit()) {
+                    digits.push(d);
+                    chars.next();
+                }
+                let n = digits
+                    .parse::<u64>()
+                    .map_err(|_| format!("number '{digits}' at column {column} is out of range"))?;
+                tokens.push((column, Token::Num(n)));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&(_, ch)) = chars
+                    .peek()
+                    .filter(|(_, ch)| ch.is_ascii_alphanumeric() || *ch == '_')
+                {
+                    ident.push(ch);
+                    chars.next();
+                }
+                let token = match ident.as_str() {
+                    "contains" => Token::Op(Op::Contains),
+                    "starts_with" => Token::Op(Op::StartsWith),
+                    "ends_with" => Token::Op(Op::EndsWith),
+                    _ => Token::Ident(ident),
+                };
+                tokens.push((column, token));
+            }
+            _ => {
+                chars.next();
+                let next = chars.peek().map(|(_, ch)| *ch);
+                let token = match (c, next) {
+                    ('=', Some('=')) => Token::Op(Op::Eq),
+                    ('!', Some('=')) => Token::Op(Op::Ne),
+                    ('<', Some('=')) => Token::Op(Op::Le),
+                    ('>', Some('=')) => Token::Op(Op::Ge),
+                    ('&', Some('&')) => Token::And,
+                    ('|', Some('|')) => Token::Or,
+                    ('<', _) => Token::Op(Op::Lt),
+                    ('>', _) => Token::Op(Op::Gt),
+                    ('!', _) => Token::Not,
+                    _ => return Err(format!("unexpected character '{c}' at column {column}")),
+                };
+                // two-character tokens consume the second character as well
+                if !matches!(token, Token::Op(Op::Lt) | Token::Op(Op::Gt) | Token::Not) {
+                    chars.next();
+                }
+                tokens.push((column, token));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Column just past the end of the input, reported for errors at the end of the expression
+    end_column: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    /// Column of the next token, or of the end of the input if there is none.
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end_column, |(column, _)| *column)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.advance();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.advance();
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let column = self.column();
+        match self.advance() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                let close = self.column();
+                match self.advance() {
+                    Some(Token::RParen) => Ok(expr),
+                    _ => Err(format!(
+                        "missing closing parenthesis for column {column} at column {close}"
+                    )),
+                }
+            }
+            Some(Token::Ident(name)) => self.comparison(&name, column),
+            Some(token) => Err(format!(
+                "expected a field name at column {column}, found {token:?}"
+            )),
+            None => Err(format!("unexpected end of expression at column {column}")),
+        }
+    }
+
+    fn comparison(&mut self, name: &str, column: usize) -> Result<Expr, String> {
+        let field = Field::parse(name)
+            .ok_or_else(|| format!("unknown field '{name}' at column {column}"))?;
+        let op_column = self.column();
+        let op = match self.advance() {
+            Some(Token::Op(op)) => op,
+            _ => {
+                return Err(format!(
+                    "expected an operator after '{name}' at column {op_column}"
+                ))
+            }
+        };
+        let value_column = self.column();
+        match (field, self.advance()) {
+            (Field::Asn, Some(Token::Num(n))) => match op {
+                Op::Contains | Op::StartsWith | Op::EndsWith => Err(format!(
+                    "operator {op:?} at column {op_column} is not supported for 'asn'"
+                )),
+                _ => Ok(Expr::Num(op, n)),
+            },
+            (Field::Asn, _) => Err(format!(
+                "'asn' must be compared with a number at column {value_column}"
+            )),
+            (_, Some(Token::Str(s))) => match op {
+                Op::Lt | Op::Le | Op::Gt | Op::Ge => Err(format!(
+                    "operator {op:?} at column {op_column} is not supported for '{name}'"
+                )),
+                _ => Ok(Expr::Str(field, op, s)),
+            },
+            _ => Err(format!(
+                "'{name}' must be compared with a string at column {value_column}"
+            )),
+        }
+    }
+}
+
+/// A parsed `--filter` expression.
+#[derive(Debug, Clone)]
+pub struct RecordFilter(Expr);
+
+impl FromStr for RecordFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end_column: s.chars().count() + 1,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!(
+                "unexpected {token:?} at column {} after expression",
+                parser.column()
+            ));
+        }
+        Ok(RecordFilter(expr))
+    }
+}
+
+fn eval(expr: &Expr, info: &AsInfo) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, info) && eval(b, info),
+        Expr::Or(a, b) => eval(a, info) || eval(b, info),
+        Expr::Not(a) => !eval(a, info),
+        Expr::Num(op, n) => {
+            let asn = info.asn as u64;
+            match op {
+                Op::Eq => asn == *n,
+                Op::Ne => asn != *n,
+                Op::Lt => asn < *n,
+                Op::Le => asn <= *n,
+                Op::Gt => asn > *n,
+                Op::Ge => asn >= *n,
+                _ => false,
+            }
+        }
+        Expr::Str(field, op, s) => {
+            let value = field.str_value(info);
+            match op {
+                Op::Eq => value == s,
+                Op::Ne => value != s,
+                Op::Contains => value.to_lowercase().contains(&s.to_lowercase()),
+                Op::StartsWith => value.to_lowercase().starts_with(&s.to_lowercase()),
+                Op::EndsWith => value.to_lowercase().ends_with(&s.to_lowercase()),
+                _ => false,
+            }
+        }
+    }
+}
+
+impl RecordFilter {
+    pub fn matches(&self, info: &AsInfo) -> bool {
+        eval(&self.0, info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bgpkit_commons::asinfo::As2orgInfo;
+
+    fn record(asn: u32, country: &str, org_name: Option<&str>) -> AsInfo {
+        AsInfo {
+            asn,
+            name: format!("AS{asn}-NAME"),
+            country: country.to_string(),
+            as2org: org_name.map(|org_name| As2orgInfo {
+                name: format!("AS{asn}-NAME"),
+                country: country.to_string(),
+                org_id: format!("ORG-{asn}"),
+                org_name: org_name.to_string(),
+            }),
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        }
+    }
+
+    fn matches(filter: &str, info: &AsInfo) -> bool {
+        filter.parse::<RecordFilter>().unwrap().matches(info)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // parsed as `country == "US" || (asn > 100 && country == "DE")`
+        let filter = r#"country == "US" || asn > 100 && country == "DE""#;
+        assert!(matches(filter, &record(1, "US", None)));
+        assert!(matches(filter, &record(200, "DE", None)));
+        assert!(!matches(filter, &record(1, "DE", None)));
+        assert!(!matches(filter, &record(200, "FR", None)));
+    }
+
+    #[test]
+    fn parentheses_and_not_override_precedence() {
+        let filter = r#"(country == "US" || asn > 100) && country == "DE""#;
+        assert!(!matches(filter, &record(1, "US", None)));
+        assert!(matches(filter, &record(200, "DE", None)));
+
+        let filter = r#"!country == "US" && asn < 10"#;
+        assert!(matches(filter, &record(1, "DE", None)));
+        assert!(!matches(filter, &record(1, "US", None)));
+        assert!(!matches(filter, &record(20, "DE", None)));
+
+        let filter = r#"!(country == "US" && asn < 10)"#;
+        assert!(matches(filter, &record(20, "US", None)));
+        assert!(!matches(filter, &record(1, "US", None)));
+    }
+
+    #[test]
+    fn string_literals_support_escapes() {
+        let info = record(1, "US", Some(r#"Acme "Widgets", Inc \ Co"#));
+        assert!(matches(r#"org_name contains "\"widgets\"""#, &info));
+        assert!(matches(r#"org_name ends_with "\\ co""#, &info));
+        assert!(matches(
+            r#"org_name == "Acme \"Widgets\", Inc \\ Co""#,
+            &info
+        ));
+        assert!(!matches(
+            r#"org_name == "acme \"widgets\", inc \\ co""#,
+            &info
+        ));
+        // operators and parentheses inside quotes are part of the literal
+        assert!(matches(
+            r#"org_name contains "s\", I" && name != "(&&)""#,
+            &info
+        ));
+    }
+
+    #[test]
+    fn org_fields_are_empty_without_as2org() {
+        assert!(matches(r#"org_name == """#, &record(1, "US", None)));
+        assert!(!matches(
+            r#"org_id starts_with "ORG""#,
+            &record(1, "US", None)
+        ));
+        assert!(matches(
+            r#"org_id starts_with "org""#,
+            &record(1, "US", Some("x"))
+        ));
+    }
+
+    #[test]
+    fn errors_report_the_column() {
+        let error = |filter: &str| filter.parse::<RecordFilter>().unwrap_err();
+        assert_eq!(
+            error(r#"country == "US" $"#),
+            "unexpected character '$' at column 17"
+        );
+        assert_eq!(
+            error(r#"asn > 1 && org_name == "abc"#),
+            "unterminated string literal at column 24"
+        );
+        assert_eq!(
+            error(r#"asn > 1 || nme == "x""#),
+            "unknown field 'nme' at column 12"
+        );
+        assert_eq!(
+            error(r#"country "US""#),
+            "expected an operator after 'country' at column 9"
+        );
+        assert_eq!(
+            error(r#"asn == "1""#),
+            "'asn' must be compared with a number at column 8"
+        );
+        assert_eq!(
+            error(r#"name < "x""#),
+            "operator Lt at column 6 is not supported for 'name'"
+        );
+        assert_eq!(
+            error(r#"(asn > 1 || asn < 5"#),
+            "missing closing parenthesis for column 1 at column 20"
+        );
+        assert_eq!(
+            error(r#"asn > 1 &&"#),
+            "unexpected end of expression at column 11"
+        );
+        assert_eq!(
+            error(r#"asn > 1 asn < 5"#),
+            "unexpected Ident(\"asn\") at column 9 after expression"
+        );
+    }
+}
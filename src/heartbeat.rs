@@ -0,0 +1,74 @@
+//! Heartbeat sent after a successful upload.
+//!
+//! By default the heartbeat is a bare `GET` of `ASNINFO_HEARTBEAT_URL`. Setting
+//! `ASNINFO_HEARTBEAT_METHOD=POST` sends `ASNINFO_HEARTBEAT_BODY` instead, with these
+//! placeholders substituted:
+//!
+//! - `{{status}}`: run status, `ok` for a successful run, `unchanged` when every upload was
+//!   skipped because the destinations already had the same content
+//! - `{{record_count}}`: number of records written
+//! - `{{elapsed_secs}}`: run time in seconds
+//! - `{{path}}`: local export path
+
+use std::time::Duration;
+use tracing::info;
+
+/// Run telemetry available to heartbeat body templates.
+pub struct HeartbeatInfo<'a> {
+    pub status: &'a str,
+    pub record_count: usize,
+    pub elapsed: Duration,
+    pub path: &'a str,
+}
+
+fn render_body(template: &str, hb: &HeartbeatInfo) -> String {
+    // values are substituted as raw text; status and path are JSON-escaped so templates
+    // can place them inside string literals
+    let escape = |s: &str| {
+        let quoted = serde_json::to_string(s).unwrap_or_default();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    template
+        .replace("{{status}}", &escape(hb.status))
+        .replace("{{record_count}}", &hb.record_count.to_string())
+        .replace(
+            "{{elapsed_secs}}",
+            &format!("{:.3}", hb.elapsed.as_secs_f64()),
+        )
+        .replace("{{path}}", &escape(hb.path))
+}
+
+/// Send the heartbeat to `url` using the configured method and body template.
+pub fn send_heartbeat(url: &str, hb: &HeartbeatInfo) -> Result<(), String> {
+    let method = dotenvy::var("ASNINFO_HEARTBEAT_METHOD")
+        .unwrap_or_else(|_| "GET".to_string())
+        .trim()
+        .to_uppercase();
+    match method.as_str() {
+        "GET" => {
+            info!("sending heartbeat to configured URL");
+            oneio::read_to_string(url)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        "POST" => {
+            let template = dotenvy::var("ASNINFO_HEARTBEAT_BODY").unwrap_or_else(|_| {
+                r#"{"status":"{{status}}","record_count":{{record_count}},"elapsed_secs":{{elapsed_secs}}}"#
+                    .to_string()
+            });
+            let body = render_body(&template, hb);
+            info!("sending POST heartbeat to configured URL");
+            reqwest::blocking::Client::new()
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body)
+                .send()
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        other => Err(format!(
+            "unsupported ASNINFO_HEARTBEAT_METHOD '{other}', use GET or POST"
+        )),
+    }
+}
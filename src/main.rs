@@ -10,72 +10,469 @@
 //! - `AWS_ENDPOINT`
 //! - `AWS_ACCESS_KEY_ID`
 //! - `AWS_SECRET_ACCESS_KEY`
-//! - `ASNINFO_UPLOAD_PATH`: full path with `s3` or `r2` prefix, such as `r2://spaces/broker/asninfo.jsonl`
+//! - `ASNINFO_UPLOAD_PATH`: full path with `s3` or `r2` prefix, such as `r2://spaces/broker/asninfo.jsonl`;
+//!   a comma-separated list uploads to every destination
 //!
 //! For Cloudflare R2 destination, `AWS_REGION` should be `auto`.
+//!
+//! When `ASNINFO_SIGNING_KEY` is set, a detached ed25519 signature is written to `<path>.sig`
+//! and uploaded alongside the data file.
 
 use bgpkit_commons::asinfo::AsInfo;
-use clap::{Parser, Subcommand};
+use bgpkit_commons::BgpkitCommons;
+use clap::{Parser, Subcommand, ValueEnum};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Formatter};
+use std::io::{IsTerminal, Read, Write};
 use std::net::SocketAddr;
 use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, Semaphore};
+use tracing::{error, info, warn};
 
+mod annotations;
 mod api;
-use crate::api::{build_router, load_asn_map_out, start_updater, AppState};
+mod avro_export;
+mod cache;
+mod checksum;
+mod config;
+mod dataset;
+mod diff;
+mod filter;
+mod heartbeat;
+mod memory;
+mod metrics;
+mod parquet_export;
+mod peeringdb;
+mod pfx2as;
+mod postgres;
+mod quality;
+mod ratelimit;
+mod rir;
+mod schema;
+mod show;
+mod signing;
+mod sqlite_export;
+mod store;
+mod upstream;
+mod validate;
+mod verify;
+use crate::annotations::Annotations;
+use crate::api::{
+    build_org_id_index, build_org_index, build_router, data_source, dataset_etag, dataset_status,
+    legacy_record, load_asn_map_out, start_updater, AppState, AsInfoOut, LoadOptions,
+    RefreshEscalation, RefreshFailurePolicy, RefreshStatus, MINIMUM_UPDATER_INTERVAL_SECS,
+};
+use crate::dataset::{
+    load_commons, load_concurrently, load_snapshot, skipped_datasets, DatasetManifest, LoadRetry,
+    DEFAULT_MAX_RETRIES, DEFAULT_RETRY_DELAY_SECS,
+};
+use crate::filter::RecordFilter;
+use crate::pfx2as::Pfx2As;
+use crate::store::RecordMap;
+use crate::upstream::UpstreamProbe;
+
+const DEFAULT_EXPORT_PATH: &str = "./asninfo.jsonl";
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
 struct Cli {
+    /// Colorize log output: auto (only when stderr is a terminal), always, or never
+    #[clap(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// TOML config file; command-line flags and environment variables override its values
+    #[clap(long, global = true, value_name = "FILE")]
+    config: Option<String>,
+
     #[clap(subcommand)]
     command: Commands,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn use_ansi(self) -> bool {
+        match self {
+            // NO_COLOR (https://no-color.org) disables color in auto mode
+            ColorMode::Auto => {
+                std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+// parsed once at startup, so the size of the `generate` variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
-    /// Generate ASN info dump file (JSON/JSONL/CSV) and optionally upload
+    /// Generate ASN info dump file (JSON/JSONL/CSV/Parquet/SQLite/MessagePack/Avro) and optionally
+    /// upload
     Generate {
-        /// Export data path; determines format by extension (json, jsonl, csv)
-        #[clap(default_value = "./asninfo.jsonl")]
-        path: String,
-        /// Simplified format (also implied when CSV)
+        /// Export data path; determines format by extension (json, jsonl, csv, parquet, db/sqlite,
+        /// msgpack/mp, avro).
+        /// Defaults to `ASNINFO_DEFAULT_PATH`, then `./asninfo.<ASNINFO_DEFAULT_FORMAT>`,
+        /// then `./asninfo.jsonl`
+        path: Option<String>,
+        /// Simplified format (also implied for CSV, Parquet, SQLite, and Avro); skips population,
+        /// hegemony, and PeeringDB data, overriding the --no-* dataset flags
         #[clap(short, long)]
         simplified: bool,
+        /// Write JSON/JSONL records in the legacy API schema (same as `?legacy=true`)
+        #[clap(long)]
+        legacy: bool,
+        /// Only export the given ASNs, as a comma-separated list (e.g. 13335,15169)
+        #[clap(long, value_delimiter = ',')]
+        only_asns: Vec<u32>,
+        /// Only export records matching an expression, e.g. `country == "US" && org_name contains "llc"`
+        #[clap(long, value_name = "EXPR")]
+        filter: Option<RecordFilter>,
+        /// Only export ASNs whose IHR hegemony score (the higher of IPv4 and IPv6) is at least
+        /// SCORE, e.g. 0.01; requires the hegemony dataset, so not with --simplified
+        #[clap(long, value_name = "SCORE")]
+        min_hegemony: Option<f64>,
+        /// Only export ASNs delegated by these RIRs (afrinic, apnic, arin, lacnic, ripe), e.g.
+        /// `ripe,arin`; loads the NRO delegation stats
+        #[clap(long, value_enum, value_delimiter = ',', value_name = "RIR,...")]
+        rir: Vec<rir::Rir>,
+        /// Only export ASNs present at one or more IXes (loads PeeringDB netixlan data)
+        #[clap(long)]
+        at_ix: bool,
+        /// Add `ix_count`/`ixs` from PeeringDB netixlan data to full JSON/JSONL records; ASNs
+        /// without IX presence get an empty list
+        #[clap(long, visible_alias = "peeringdb-detail")]
+        with_ix: bool,
+        /// PeeringDB endpoints to load, comma-separated (net, netixlan); default: net only
+        #[clap(long, value_delimiter = ',')]
+        peeringdb_endpoints: Vec<String>,
+        /// Export a random sample: a fraction in (0, 1), e.g. 0.05, or a record count, e.g. 500
+        #[clap(long, value_name = "FRACTION|COUNT")]
+        sample: Option<SampleSize>,
+        /// Seed for --sample to make the selection reproducible
+        #[clap(long, requires = "sample")]
+        seed: Option<u64>,
+        /// Also write a gzipped copy to `<path>.gz` in the same pass, uploaded alongside
+        #[clap(long)]
+        also_gzip: bool,
+        /// Fail with exit code 12 if any requested dataset was skipped or failed to load
+        #[clap(long)]
+        require_all_datasets: bool,
+        /// Drop `country_name` and keep only country codes (skips loading country data)
+        #[clap(long)]
+        compact_country: bool,
+        /// Skip the population dataset (--simplified already skips it)
+        #[clap(long)]
+        no_population: bool,
+        /// Skip the hegemony dataset (--simplified already skips it)
+        #[clap(long)]
+        no_hegemony: bool,
+        /// Skip the PeeringDB net dataset (--simplified already skips it)
+        #[clap(long)]
+        no_peeringdb: bool,
+        /// Abort with exit code 8 if resident memory exceeds this many MiB
+        #[clap(long, value_name = "MIB")]
+        max_memory: Option<u64>,
+        /// Dataset source manifest (JSON); defaults to ASNINFO_MANIFEST if set
+        #[clap(long)]
+        manifest: Option<String>,
+        /// JSON file mapping ASN to an operator note, added as a `note` field (JSON/JSONL only)
+        #[clap(long)]
+        annotations: Option<String>,
+        /// For uncompressed JSONL, also write `<path>.index.json` mapping each ASN to its byte
+        /// offset and length
+        #[clap(long)]
+        emit_index: bool,
+        /// Log a warning if the dataset has fewer records than this
+        #[clap(long, value_name = "N")]
+        warn_threshold: Option<usize>,
+        /// Fail with exit code 11 if the dataset has fewer records than this; defaults to 90% of
+        /// the previously uploaded file's record count when it can be fetched
+        #[clap(long, value_name = "N")]
+        fail_threshold: Option<usize>,
+        /// Validate each record against this JSON Schema before writing; exit code 13 on violation
+        #[clap(long, value_name = "SCHEMA")]
+        validate_schema: Option<String>,
+        /// For JSON, write one object of index-aligned arrays keyed by field name instead of an
+        /// array of records
+        #[clap(long)]
+        columnar: bool,
+        /// Write per-ASN enrichment problems (e.g. unknown country codes) to this JSONL file
+        #[clap(long, value_name = "PATH")]
+        emit_errors_file: Option<String>,
+        /// List every ASN without an as2org record in the errors file instead of a single count
+        #[clap(long)]
+        errors_verbose: bool,
+        /// Target table when PATH is a postgres:// URL; created if absent and replaced atomically
+        #[clap(long, default_value = "asninfo")]
+        pg_table: String,
+        /// Indent JSON exports for manual inspection (JSON only)
+        #[clap(long)]
+        pretty: bool,
+        /// CSV columns to write, in order, comma-separated (e.g. asn,as_name,country_code);
+        /// default: all columns
+        #[clap(long, value_delimiter = ',')]
+        columns: Vec<String>,
+        /// Load records from a previous full-schema JSON/JSONL dump or CSV export instead of the
+        /// upstream sources; no network access, country names are left empty
+        #[clap(long, value_name = "FILE")]
+        from_file: Option<String>,
+        /// Retry a failed dataset load this many times, with exponential backoff
+        #[clap(long, default_value_t = DEFAULT_MAX_RETRIES)]
+        max_retries: u32,
+        /// Delay before the first retry in seconds, doubled after each further failure
+        #[clap(long, default_value_t = DEFAULT_RETRY_DELAY_SECS)]
+        retry_delay_secs: u64,
+        /// Skip writing (and uploading) the `<PATH>.sha256` checksum file
+        #[clap(long)]
+        no_checksum: bool,
+        /// Prefix CSV output with a UTF-8 byte order mark, for spreadsheet tools on Windows
+        #[clap(long)]
+        bom: bool,
+        /// Add the as2org organization country and as2org AS name (`org_country`,
+        /// `as2org_name`) to simplified JSON/JSONL and CSV output
+        #[clap(long)]
+        full_org: bool,
+        /// Load and serialize everything, then report the record count and output size without
+        /// writing any file or uploading
+        #[clap(long)]
+        dry_run: bool,
+        /// Output format (json, jsonl, csv, parquet, sqlite, msgpack, avro) instead of inferring it
+        /// from the PATH extension; writing to stdout (PATH `-`) defaults to jsonl
+        #[clap(long)]
+        format: Option<String>,
+        /// Record order: asn (default), country (country code, then ASN), or org (org name, then
+        /// ASN; ASNs without an org last)
+        #[clap(long, value_enum, default_value_t = SortKey::Asn)]
+        sort_by: SortKey,
+        /// Only export the first N records after sorting, for quick smoke tests; the upload is
+        /// skipped so truncated data is never published
+        #[clap(long, value_name = "N")]
+        limit: Option<usize>,
+        /// Upload even when a destination's `.sha256` sidecar shows it already has the same content
+        #[clap(long)]
+        force_upload: bool,
     },
     /// Serve an HTTP API for ASN info lookup
     Serve {
-        /// Bind address, e.g., 0.0.0.0:8080
-        #[clap(short, long, default_value = "0.0.0.0:8080")]
-        bind: String,
+        /// Bind address, e.g., 0.0.0.0:8080 (the default), or a Unix socket path as
+        /// unix:/run/asninfo.sock
+        #[clap(short, long)]
+        bind: Option<String>,
         /// Refresh interval in seconds for background updates, default 21600 (6 hours)
-        #[clap(long, default_value_t = 21600)]
-        refresh_secs: u64,
+        #[clap(long)]
+        refresh_secs: Option<u64>,
+        /// Refresh early when data older than this many seconds is queried (minimum 600)
+        #[clap(long)]
+        entry_ttl_secs: Option<u64>,
         /// Use simplified mode (skip heavy datasets); default false
         #[clap(long, default_value_t = false)]
         simplified: bool,
+        /// Add a `countries` field listing every country observed for an ASN
+        #[clap(long, default_value_t = false)]
+        with_geo: bool,
+        /// Load PeeringDB IX presence, adding `ix_count`/`ixs` and enabling `at_ix=true`
+        #[clap(long, default_value_t = false, visible_alias = "peeringdb-detail")]
+        with_ix: bool,
+        /// PeeringDB endpoints to load, comma-separated (net, netixlan); default: net only
+        #[clap(long, value_delimiter = ',')]
+        peeringdb_endpoints: Vec<String>,
+        /// Omit `country_name` from responses and skip loading country data
+        #[clap(long, default_value_t = false)]
+        compact_country: bool,
+        /// Add a `continent` code from the country record (e.g. NA, EU)
+        #[clap(long, default_value_t = false, conflicts_with = "compact_country")]
+        with_continent: bool,
+        /// Dataset source manifest (JSON); defaults to ASNINFO_MANIFEST if set
+        #[clap(long)]
+        manifest: Option<String>,
+        /// JSON file mapping ASN to an operator note, added as a `note` field; reloaded on change
+        #[clap(long)]
+        annotations: Option<String>,
+        /// Load prefix-to-origin data for IP-to-ASN resolution, enabling `/domain/{name}`
+        #[clap(long, default_value_t = false)]
+        with_pfx2as: bool,
+        /// Keep records zstd-compressed in memory, decompressing on each access (see README for the
+        /// memory and latency trade-off)
+        #[clap(long, default_value_t = false)]
+        compress_records: bool,
+        /// Enable `/health/upstream`, which probes the upstream data sources with HEAD requests
+        #[clap(long, default_value_t = false)]
+        probe_upstream: bool,
+        /// How long `/health/upstream` results are cached, in seconds
+        #[clap(long, default_value_t = 300, requires = "probe_upstream")]
+        upstream_probe_ttl_secs: u64,
+        /// Escalate after this many consecutive failed refreshes (see --on-refresh-failures)
+        #[clap(long, value_name = "N")]
+        max_refresh_failures: Option<u32>,
+        /// Escalation after --max-refresh-failures: exit (code 14) or exponential backoff
+        #[clap(long, value_enum, default_value_t = RefreshEscalation::Exit)]
+        on_refresh_failures: RefreshEscalation,
+        /// Load records from a previous full-schema JSON/JSONL dump or CSV export instead of the
+        /// upstream sources, re-read on every refresh; country names are left empty
+        #[clap(long, value_name = "FILE")]
+        from_file: Option<String>,
+        /// Retry a failed dataset load this many times, with exponential backoff
+        #[clap(long, default_value_t = DEFAULT_MAX_RETRIES)]
+        max_retries: u32,
+        /// Delay before the first retry in seconds, doubled after each further failure
+        #[clap(long, default_value_t = DEFAULT_RETRY_DELAY_SECS)]
+        retry_delay_secs: u64,
+        /// Persist loaded records to this directory and, on startup, serve them from there while
+        /// they are younger than the refresh interval
+        #[clap(long, value_name = "DIR")]
+        cache_dir: Option<String>,
+    },
+    /// Periodically regenerate the dump file and upload it, without the HTTP server
+    Daemon {
+        /// Export data path; same defaults and format rules as `generate`
+        path: Option<String>,
+        /// Interval in seconds between runs, default 21600 (6 hours, minimum 3600)
+        #[clap(long)]
+        refresh_secs: Option<u64>,
+        /// Simplified format (also implied for CSV, Parquet, and SQLite)
+        #[clap(short, long)]
+        simplified: bool,
+        /// Dataset source manifest (JSON); defaults to ASNINFO_MANIFEST if set
+        #[clap(long)]
+        manifest: Option<String>,
+    },
+    /// Compare a running server against a generated dump file (JSON/JSONL)
+    Verify {
+        /// Base URL of the running server, e.g., http://localhost:8080
+        url: String,
+        /// Dump file to compare against
+        file: String,
+        /// Number of ASNs per lookup request; must not exceed the server's ASNINFO_MAX_ASNS
+        #[clap(long, default_value_t = 100)]
+        batch_size: usize,
+    },
+    /// Check that a full-schema JSON/JSONL dump parses cleanly with unique, sorted ASNs
+    Validate {
+        /// Dump file to check; local path or remote URL
+        file: String,
+        /// Also check the file against its `<FILE>.sha256` sidecar (local files only)
+        #[clap(long)]
+        checksum: bool,
+    },
+    /// Pretty-print the record of a single ASN from a dump file (JSON/JSONL/CSV)
+    Show {
+        /// Dump file to search; local path or remote URL
+        file: String,
+        /// ASN to look up
+        asn: u32,
+    },
+    /// Report ASNs added, removed, or changed (name, country, org_name) between two full-schema
+    /// JSON/JSONL dumps, as JSONL on stdout
+    Diff {
+        /// Older dump; local path or remote URL
+        old: String,
+        /// Newer dump; local path or remote URL
+        new: String,
+        /// Print counts per change type instead of one line per changed ASN
+        #[clap(long)]
+        summary: bool,
     },
 }
 
+/// Simplified record schema.
+///
+/// Missing values are `null` (`None`), never empty strings: `org_id`/`org_name` are `null` when
+/// the ASN has no as2org record, and `country_name` is `null` when the country code is unknown.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AsInfoSimplified {
     pub asn: u32,
     pub as_name: String,
-    pub org_id: String,
-    pub org_name: String,
+    pub org_id: Option<String>,
+    pub org_name: Option<String>,
     pub country_code: String,
-    pub country_name: String,
+    pub country_name: Option<String>,
     pub data_source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Country of the as2org organization, which may differ from the ASN's registration
+    /// country; only written with `--full-org`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_country: Option<String>,
+    /// AS name as recorded in the as2org dataset; only written with `--full-org`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as2org_name: Option<String>,
+}
+
+/// Columns of the CSV export, in order.
+const CSV_COLUMNS: [&str; 7] = [
+    "asn",
+    "as_name",
+    "org_id",
+    "org_name",
+    "country_code",
+    "country_name",
+    "data_source",
+];
+
+/// Extra CSV columns available with `--full-org`, appended after [`CSV_COLUMNS`] by default.
+const FULL_ORG_CSV_COLUMNS: [&str; 2] = ["org_country", "as2org_name"];
+
+impl AsInfoSimplified {
+    /// The value of a CSV column; missing values are empty.
+    fn csv_field(&self, column: &str) -> String {
+        let value = match column {
+            "asn" => self.asn.to_string(),
+            "as_name" => self.as_name.clone(),
+            "org_id" => self.org_id.clone().unwrap_or_default(),
+            "org_name" => self.org_name.clone().unwrap_or_default(),
+            "country_code" => self.country_code.clone(),
+            "country_name" => self.country_name.clone().unwrap_or_default(),
+            "data_source" => self.data_source.clone(),
+            "org_country" => self.org_country.clone().unwrap_or_default(),
+            "as2org_name" => self.as2org_name.clone().unwrap_or_default(),
+            _ => String::new(),
+        };
+        single_line(value)
+    }
+
+    /// Drop the as2org fields that are only written with `--full-org`.
+    fn without_full_org(mut self) -> Self {
+        self.org_country = None;
+        self.as2org_name = None;
+        self
+    }
+}
+
+/// Replace line breaks (`\r\n`, `\r`, or `\n`) with a space, so names with embedded newlines
+/// keep each CSV record on one line for line-oriented tools.
+fn single_line(value: String) -> String {
+    if !value.contains(['\r', '\n']) {
+        return value;
+    }
+    value.replace("\r\n", " ").replace(['\r', '\n'], " ")
 }
 
 impl From<&AsInfo> for AsInfoSimplified {
     fn from(value: &AsInfo) -> Self {
-        let (org_id, org_name) = match &value.as2org {
-            None => ("".to_string(), "".to_string()),
-            Some(v) => (v.org_id.clone(), v.org_name.clone()),
+        let (org_id, org_name, org_country, as2org_name) = match &value.as2org {
+            None => (None, None, None, None),
+            Some(v) => (
+                Some(v.org_id.clone()),
+                Some(v.org_name.clone()),
+                Some(v.country.clone()),
+                Some(v.name.clone()),
+            ),
         };
 
         AsInfoSimplified {
@@ -84,8 +481,11 @@ impl From<&AsInfo> for AsInfoSimplified {
             org_id,
             org_name,
             country_code: value.country.clone(),
-            country_name: "".to_string(),
-            data_source: "".to_string(),
+            country_name: None,
+            data_source: data_source(value),
+            note: None,
+            org_country,
+            as2org_name,
         }
     }
 }
@@ -96,6 +496,47 @@ enum ExportFormat {
     JSON,
     JSONL,
     CSV,
+    Parquet,
+    Sqlite,
+    MsgPack,
+    Avro,
+    Postgres,
+}
+
+impl ExportFormat {
+    /// Determine the format from the file extension, ignoring a compression suffix, so
+    /// `asninfo.jsonl.gz` is JSONL and `asninfo.json.zst` is JSON.
+    fn from_path(path: &str) -> Option<Self> {
+        let (base, _) = split_compression_suffix(path);
+        let (_, extension) = base.rsplit_once('.')?;
+        Self::from_name(extension)
+    }
+
+    /// Parse a format name as given to `--format`, which is also its file extension.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().trim_start_matches('.').to_lowercase().as_str() {
+            "jsonl" => Some(ExportFormat::JSONL),
+            "json" => Some(ExportFormat::JSON),
+            "csv" => Some(ExportFormat::CSV),
+            "parquet" => Some(ExportFormat::Parquet),
+            "db" | "sqlite" => Some(ExportFormat::Sqlite),
+            "msgpack" | "mp" => Some(ExportFormat::MsgPack),
+            "avro" => Some(ExportFormat::Avro),
+            _ => None,
+        }
+    }
+
+    /// Whether records are serialized through the `oneio` writer; Parquet, SQLite, Avro, and
+    /// Postgres exports write to their destination directly.
+    fn uses_writer(self) -> bool {
+        !matches!(
+            self,
+            ExportFormat::Parquet
+                | ExportFormat::Sqlite
+                | ExportFormat::Avro
+                | ExportFormat::Postgres
+        )
+    }
 }
 
 impl Display for ExportFormat {
@@ -110,6 +551,21 @@ impl Display for ExportFormat {
             ExportFormat::CSV => {
                 write!(f, "csv")
             }
+            ExportFormat::Parquet => {
+                write!(f, "parquet")
+            }
+            ExportFormat::Sqlite => {
+                write!(f, "sqlite")
+            }
+            ExportFormat::MsgPack => {
+                write!(f, "msgpack")
+            }
+            ExportFormat::Avro => {
+                write!(f, "avro")
+            }
+            ExportFormat::Postgres => {
+                write!(f, "postgres")
+            }
         }
     }
 }
@@ -120,106 +576,1129 @@ fn sanitize_url(raw: &str) -> String {
         .to_string()
 }
 
-#[tokio::main]
-async fn main() {
-    tracing_subscriber::fmt().with_ansi(false).init();
-    dotenvy::dotenv().ok();
+/// Resolve the default export path when none is given on the command line.
+///
+/// `ASNINFO_DEFAULT_PATH` takes precedence over `ASNINFO_DEFAULT_FORMAT`; the latter only picks
+/// the extension of the built-in `./asninfo.<format>` path.
+fn default_export_path() -> String {
+    if let Ok(raw) = dotenvy::var("ASNINFO_DEFAULT_PATH") {
+        let path = raw.trim();
+        if !path.is_empty() {
+            return path.to_string();
+        }
+    }
+    if let Ok(raw) = dotenvy::var("ASNINFO_DEFAULT_FORMAT") {
+        let format = raw.trim().trim_start_matches('.').to_lowercase();
+        if !format.is_empty() {
+            return format!("./asninfo.{format}");
+        }
+    }
+    DEFAULT_EXPORT_PATH.to_string()
+}
 
+fn main() {
     let cli = Cli::parse();
 
+    // logs go to stderr so `generate -` and the other stdout commands can be piped
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(cli.color.use_ansi())
+        .init();
+    dotenvy::dotenv().ok();
+    let config = match cli.config.as_deref().map(config::Config::load) {
+        None => config::Config::default(),
+        Some(Ok(config)) => config,
+        Some(Err(code)) => exit(code),
+    };
+    // the environment is only safe to modify while the process is single-threaded, so .env and
+    // the config file are applied before the runtime starts its worker threads
+    config.apply_env();
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            error!("failed to start the async runtime: {e}");
+            exit(1);
+        }
+    };
+    runtime.block_on(run(cli, config));
+}
+
+async fn run(cli: Cli, config: config::Config) {
     match cli.command {
-        Commands::Generate { path, simplified } => {
-            if let Err(code) = generate_cmd(&path, simplified) {
+        Commands::Generate {
+            path,
+            simplified,
+            legacy,
+            only_asns,
+            filter,
+            min_hegemony,
+            rir,
+            at_ix,
+            with_ix,
+            peeringdb_endpoints,
+            sample,
+            seed,
+            also_gzip,
+            require_all_datasets,
+            compact_country,
+            no_population,
+            no_hegemony,
+            no_peeringdb,
+            max_memory,
+            manifest,
+            annotations,
+            emit_index,
+            warn_threshold,
+            fail_threshold,
+            validate_schema,
+            columnar,
+            emit_errors_file,
+            errors_verbose,
+            pg_table,
+            pretty,
+            columns,
+            from_file,
+            max_retries,
+            retry_delay_secs,
+            no_checksum,
+            bom,
+            full_org,
+            dry_run,
+            format,
+            sort_by,
+            limit,
+            force_upload,
+        } => {
+            let path = path.unwrap_or_else(default_export_path);
+            let _memory_guard = max_memory.and_then(memory::start_memory_guard);
+            let mut manifest = match load_manifest(manifest, &config) {
+                Ok(m) => m,
+                Err(code) => exit(code),
+            };
+            let with_ix =
+                peeringdb::select_endpoints(&peeringdb_endpoints, &mut manifest) || with_ix;
+            // the flags only turn datasets off; --simplified skips all three regardless
+            manifest.population.enabled &= !no_population;
+            manifest.hegemony.enabled &= !no_hegemony;
+            manifest.peeringdb.enabled &= !no_peeringdb;
+            let annotations = match annotations.as_deref().map(annotations::load_annotations) {
+                None => Annotations::default(),
+                Some(Ok(a)) => a,
+                Some(Err(code)) => exit(code),
+            };
+            let opts = GenerateOptions {
+                simplified,
+                legacy,
+                only_asns,
+                filter,
+                min_hegemony,
+                rir,
+                at_ix,
+                with_ix,
+                sample,
+                seed,
+                also_gzip,
+                require_all_datasets,
+                compact_country,
+                manifest,
+                annotations,
+                emit_index,
+                warn_threshold,
+                fail_threshold,
+                validate_schema,
+                columnar,
+                emit_errors_file,
+                errors_verbose,
+                pg_table,
+                pretty,
+                columns,
+                from_file,
+                retry: LoadRetry {
+                    max_retries,
+                    base_delay: Duration::from_secs(retry_delay_secs),
+                },
+                no_checksum,
+                bom,
+                full_org,
+                dry_run,
+                format,
+                sort_by,
+                limit,
+                force_upload,
+            };
+            let result = tokio::task::spawn_blocking(move || generate_cmd(&path, &opts))
+                .await
+                .unwrap_or(Err(1));
+            if let Err(code) = result {
                 exit(code);
             }
         }
         Commands::Serve {
             bind,
             refresh_secs,
+            entry_ttl_secs,
+            simplified,
+            with_geo,
+            with_ix,
+            peeringdb_endpoints,
+            compact_country,
+            with_continent,
+            manifest,
+            annotations,
+            with_pfx2as,
+            compress_records,
+            probe_upstream,
+            upstream_probe_ttl_secs,
+            max_refresh_failures,
+            on_refresh_failures,
+            from_file,
+            max_retries,
+            retry_delay_secs,
+            cache_dir,
+        } => {
+            let mut manifest = match load_manifest(manifest, &config) {
+                Ok(m) => m,
+                Err(code) => exit(code),
+            };
+            let with_ix =
+                peeringdb::select_endpoints(&peeringdb_endpoints, &mut manifest) || with_ix;
+            if cache_dir.is_some() && from_file.is_some() {
+                warn!("--cache-dir has no effect with --from-file");
+            }
+            let opts = LoadOptions {
+                simplified,
+                with_geo,
+                with_ix,
+                compact_country,
+                with_continent,
+                manifest,
+                compress_records,
+                from_file,
+                retry: LoadRetry {
+                    max_retries,
+                    base_delay: Duration::from_secs(retry_delay_secs),
+                },
+                cache_dir,
+            };
+            let upstream_probe = probe_upstream.then(|| {
+                Arc::new(UpstreamProbe::new(
+                    &opts.manifest,
+                    opts.simplified,
+                    Duration::from_secs(upstream_probe_ttl_secs),
+                ))
+            });
+            let serve_opts = ServeOptions {
+                refresh_secs: refresh_secs
+                    .or(config.refresh_secs)
+                    .unwrap_or(config::DEFAULT_REFRESH_SECS),
+                entry_ttl_secs,
+                annotations_path: annotations,
+                with_pfx2as,
+                upstream_probe,
+                failure_policy: RefreshFailurePolicy {
+                    max_failures: max_refresh_failures,
+                    escalation: on_refresh_failures,
+                },
+            };
+            let bind = bind
+                .or_else(|| config.bind.clone())
+                .unwrap_or_else(|| config::DEFAULT_BIND.to_string());
+            if let Err(code) = serve_cmd(&bind, opts, serve_opts).await {
+                exit(code);
+            }
+        }
+        Commands::Daemon {
+            path,
+            refresh_secs,
             simplified,
+            manifest,
+        } => {
+            let path = path.unwrap_or_else(default_export_path);
+            let manifest = match load_manifest(manifest, &config) {
+                Ok(m) => m,
+                Err(code) => exit(code),
+            };
+            let opts = GenerateOptions {
+                simplified,
+                manifest,
+                ..Default::default()
+            };
+            let refresh_secs = refresh_secs
+                .or(config.refresh_secs)
+                .unwrap_or(config::DEFAULT_REFRESH_SECS);
+            daemon_cmd(path, refresh_secs, opts).await;
+        }
+        Commands::Verify {
+            url,
+            file,
+            batch_size,
         } => {
-            if let Err(code) = serve_cmd(&bind, refresh_secs, simplified).await {
+            let result =
+                tokio::task::spawn_blocking(move || verify::verify_cmd(&url, &file, batch_size))
+                    .await
+                    .unwrap_or(Err(1));
+            if let Err(code) = result {
                 exit(code);
             }
         }
+        Commands::Validate { file, checksum } => {
+            let result =
+                tokio::task::spawn_blocking(move || validate::validate_cmd(&file, checksum))
+                    .await
+                    .unwrap_or(Err(1));
+            if let Err(code) = result {
+                exit(code);
+            }
+        }
+        Commands::Show { file, asn } => {
+            let result = tokio::task::spawn_blocking(move || show::show_cmd(&file, asn))
+                .await
+                .unwrap_or(Err(1));
+            if let Err(code) = result {
+                exit(code);
+            }
+        }
+        Commands::Diff { old, new, summary } => {
+            let result = tokio::task::spawn_blocking(move || diff::diff_cmd(&old, &new, summary))
+                .await
+                .unwrap_or(Err(1));
+            if let Err(code) = result {
+                exit(code);
+            }
+        }
+    }
+}
+
+/// Load the dataset manifest from `--manifest` or `ASNINFO_MANIFEST`, or use defaults, and apply
+/// the config file's dataset toggles.
+fn load_manifest(path: Option<String>, config: &config::Config) -> Result<DatasetManifest, i32> {
+    let mut manifest = match path.or_else(|| dotenvy::var("ASNINFO_MANIFEST").ok()) {
+        Some(path) => DatasetManifest::from_path(&path)?,
+        None => DatasetManifest::default(),
+    };
+    config.apply_datasets(&mut manifest);
+    Ok(manifest)
+}
+
+/// Options for the `generate` subcommand besides the output path.
+#[derive(Clone, Default)]
+struct GenerateOptions {
+    simplified: bool,
+    legacy: bool,
+    only_asns: Vec<u32>,
+    filter: Option<RecordFilter>,
+    min_hegemony: Option<f64>,
+    rir: Vec<rir::Rir>,
+    at_ix: bool,
+    with_ix: bool,
+    sample: Option<SampleSize>,
+    seed: Option<u64>,
+    also_gzip: bool,
+    require_all_datasets: bool,
+    compact_country: bool,
+    manifest: DatasetManifest,
+    annotations: Annotations,
+    emit_index: bool,
+    warn_threshold: Option<usize>,
+    fail_threshold: Option<usize>,
+    validate_schema: Option<String>,
+    columnar: bool,
+    emit_errors_file: Option<String>,
+    errors_verbose: bool,
+    pg_table: String,
+    pretty: bool,
+    columns: Vec<String>,
+    from_file: Option<String>,
+    retry: LoadRetry,
+    no_checksum: bool,
+    bom: bool,
+    full_org: bool,
+    dry_run: bool,
+    format: Option<String>,
+    sort_by: SortKey,
+    limit: Option<usize>,
+    force_upload: bool,
+}
+
+/// Order of the records in a `generate` export.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum SortKey {
+    #[default]
+    Asn,
+    Country,
+    Org,
+}
+
+impl SortKey {
+    /// Compare two records; ties are broken by ASN, so the order is total and the same on every
+    /// run for the same input.
+    fn compare(self, a: &AsInfo, b: &AsInfo) -> std::cmp::Ordering {
+        let org = |v: &AsInfo| v.as2org.as_ref().map(|o| o.org_name.to_lowercase());
+        match self {
+            SortKey::Asn => std::cmp::Ordering::Equal,
+            SortKey::Country => a.country.cmp(&b.country),
+            // records without an org sort after all named orgs
+            SortKey::Org => match (org(a), org(b)) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            },
+        }
+        .then(a.asn.cmp(&b.asn))
+    }
+}
+
+/// Size of a random sample, given as a fraction of all records or an absolute count.
+#[derive(Debug, Clone, Copy)]
+enum SampleSize {
+    Fraction(f64),
+    Count(usize),
+}
+
+impl FromStr for SampleSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(count) = s.parse::<usize>() {
+            return Ok(SampleSize::Count(count));
+        }
+        match s.parse::<f64>() {
+            Ok(f) if f > 0.0 && f < 1.0 => Ok(SampleSize::Fraction(f)),
+            _ => Err(format!(
+                "'{s}' is neither a fraction between 0 and 1 nor a record count"
+            )),
+        }
+    }
+}
+
+impl SampleSize {
+    fn count_of(self, total: usize) -> usize {
+        match self {
+            SampleSize::Fraction(f) => ((total as f64) * f).round() as usize,
+            SampleSize::Count(n) => n.min(total),
+        }
+    }
+}
+
+/// Resolve `--columns` against [`CSV_COLUMNS`] (plus [`FULL_ORG_CSV_COLUMNS`] with
+/// `--full-org`); no requested columns means all of them.
+fn csv_columns(
+    requested: &[String],
+    compact_country: bool,
+    full_org: bool,
+) -> Result<Vec<&'static str>, i32> {
+    let available = CSV_COLUMNS
+        .into_iter()
+        .chain(FULL_ORG_CSV_COLUMNS.into_iter().filter(|_| full_org));
+    if requested.is_empty() {
+        return Ok(available
+            .filter(|c| !(compact_country && *c == "country_name"))
+            .collect());
+    }
+    let mut columns = Vec::with_capacity(requested.len());
+    for name in requested {
+        let Some(column) = available.clone().find(|c| *c == name.trim()) else {
+            if FULL_ORG_CSV_COLUMNS.contains(&name.trim()) {
+                error!("CSV column '{name}' requires --full-org");
+                return Err(1);
+            }
+            error!(
+                "unknown CSV column '{name}', valid columns are: {}",
+                available.collect::<Vec<_>>().join(", ")
+            );
+            return Err(1);
+        };
+        if compact_country && column == "country_name" {
+            error!("CSV column 'country_name' is not available with --compact-country");
+            return Err(1);
+        }
+        columns.push(column);
+    }
+    Ok(columns)
+}
+
+/// Exit code used when the dataset is smaller than the fail threshold.
+const DATASET_TOO_SMALL_EXIT_CODE: i32 = 11;
+/// Exit code used when `--require-all-datasets` is set and a dataset was skipped.
+const DATASET_SKIPPED_EXIT_CODE: i32 = 12;
+
+/// Count the records in a previously generated JSON, JSONL, or CSV file.
+fn count_records(path: &str) -> Option<usize> {
+    let format = ExportFormat::from_path(path)?;
+    if matches!(format, ExportFormat::MsgPack) {
+        let reader = oneio::get_reader(path).ok()?;
+        return rmp_serde::from_read::<_, Vec<serde::de::IgnoredAny>>(reader)
+            .ok()
+            .map(|v| v.len());
+    }
+    let content = oneio::read_to_string(path).ok()?;
+    match format {
+        ExportFormat::JSONL => Some(content.lines().filter(|l| !l.trim().is_empty()).count()),
+        // quoted fields may span lines, so count parsed records rather than lines
+        ExportFormat::CSV => Some(
+            csv::Reader::from_reader(content.as_bytes())
+                .records()
+                .count(),
+        ),
+        ExportFormat::JSON => serde_json::from_str::<Vec<Value>>(&content)
+            .ok()
+            .map(|v| v.len()),
+        ExportFormat::MsgPack
+        | ExportFormat::Parquet
+        | ExportFormat::Sqlite
+        | ExportFormat::Avro
+        | ExportFormat::Postgres => None,
+    }
+}
+
+/// Check the loaded record count against the configured thresholds.
+fn check_dataset_size(count: usize, opts: &GenerateOptions) -> Result<(), i32> {
+    let fail_threshold = opts.fail_threshold.or_else(|| {
+        let upload_path = upload_destinations().into_iter().next()?;
+        let previous = count_records(&upload_path)?;
+        info!("previously uploaded file has {previous} records");
+        Some(previous * 9 / 10)
+    });
+    if let Some(threshold) = fail_threshold {
+        if count < threshold {
+            error!(
+                "dataset has {count} records, below fail threshold {threshold}; refusing to export"
+            );
+            return Err(DATASET_TOO_SMALL_EXIT_CODE);
+        }
+    }
+    if let Some(threshold) = opts.warn_threshold {
+        if count < threshold {
+            warn!("dataset has {count} records, below warn threshold {threshold}");
+        }
+    }
+    Ok(())
+}
+
+/// Writes every byte to both inner writers, so one serialization pass produces two files.
+struct TeeWriter<A: Write, B: Write>(A, B);
+
+impl<A: Write, B: Write> Write for TeeWriter<A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// `generate` PATH that writes the export to stdout.
+const STDOUT_PATH: &str = "-";
+
+/// Discards everything written to it, counting the bytes; used for `--dry-run`.
+struct CountingWriter(Arc<AtomicU64>);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compression suffixes that `oneio` applies transparently when reading and writing.
+const COMPRESSION_SUFFIXES: [&str; 5] = [".gz", ".bz2", ".zst", ".lz4", ".xz"];
+
+/// Split `path` into the part before its compression suffix and the suffix, if any.
+fn split_compression_suffix(path: &str) -> (&str, Option<&'static str>) {
+    for suffix in COMPRESSION_SUFFIXES {
+        if let Some(base) = path.strip_suffix(suffix) {
+            return (base, Some(suffix));
+        }
+    }
+    (path, None)
+}
+
+fn is_compressed_path(path: &str) -> bool {
+    split_compression_suffix(path).1.is_some()
+}
+
+/// Write `rows` as CSV with a header of `columns`, quoting fields only where RFC 4180 requires
+/// it; with `bom`, the file starts with a UTF-8 byte order mark.
+fn write_csv(
+    writer: &mut impl Write,
+    columns: &[&str],
+    rows: impl Iterator<Item = AsInfoSimplified>,
+    bom: bool,
+) -> Result<(), i32> {
+    if bom && writer.write_all("\u{feff}".as_bytes()).is_err() {
+        error!("failed to write to file");
+        return Err(1);
+    }
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    if let Err(e) = csv_writer.write_record(columns) {
+        error!("failed to write CSV header: {e}");
+        return Err(1);
+    }
+    for info in rows {
+        let row = columns.iter().map(|c| info.csv_field(c));
+        if let Err(e) = csv_writer.write_record(row) {
+            error!("failed to write CSV record for AS{}: {e}", info.asn);
+            return Err(1);
+        }
+    }
+    if let Err(e) = csv_writer.flush() {
+        error!("failed to write CSV file: {e}");
+        return Err(1);
+    }
+    Ok(())
+}
+
+/// Write `values` as a JSON array, element by element instead of serializing it as a whole, so
+/// the export stays streamed. With `pretty`, the output matches `serde_json::to_string_pretty`
+/// of the whole array.
+fn write_json_array(
+    writer: &mut impl Write,
+    values: impl Iterator<Item = Value>,
+    pretty: bool,
+) -> Result<(), i32> {
+    if write!(writer, "[").is_err() {
+        error!("failed to write to file");
+        return Err(1);
+    }
+    let mut empty = true;
+    for (i, as_info) in values.enumerate() {
+        let separator = match (i > 0, pretty) {
+            (true, true) => ",\n",
+            (true, false) => ",",
+            (false, true) => "\n",
+            (false, false) => "",
+        };
+        if write!(writer, "{separator}").is_err() {
+            error!("failed to write to file");
+            return Err(1);
+        }
+        let serialized = match pretty {
+            // indent each record one level, as serde_json does for array elements
+            true => serde_json::to_string_pretty(&as_info)
+                .map(|s| format!("  {}", s.replace('\n', "\n  "))),
+            false => serde_json::to_string(&as_info),
+        };
+        match serialized {
+            Ok(s) => {
+                if write!(writer, "{s}").is_err() {
+                    error!("failed to write to file");
+                    return Err(1);
+                }
+            }
+            Err(e) => {
+                error!("failed to serialize AS info: {}", e);
+                return Err(1);
+            }
+        }
+        empty = false;
+    }
+    if pretty && !empty && writeln!(writer).is_err() {
+        error!("failed to write to file");
+        return Err(1);
+    }
+    if writeln!(writer, "]").is_err() {
+        error!("failed to write to file");
+        return Err(1);
+    }
+    Ok(())
+}
+
+/// Write `values` as JSON Lines, one record per line with every line, including the last, ending
+/// in `\n`. With `emit_index`, returns the byte range of each record keyed by ASN.
+fn write_jsonl(
+    writer: &mut impl Write,
+    values: impl Iterator<Item = Value>,
+    emit_index: bool,
+) -> Result<BTreeMap<u32, IndexEntry>, i32> {
+    let mut index: BTreeMap<u32, IndexEntry> = BTreeMap::new();
+    let mut offset: u64 = 0;
+    for as_info in values {
+        match serde_json::to_string(&as_info) {
+            Ok(s) => {
+                if writeln!(writer, "{}", s).is_err() {
+                    error!("failed to write to file");
+                    return Err(1);
+                }
+                let length = s.len() as u64 + 1;
+                if emit_index {
+                    if let Some(asn) = as_info["asn"].as_u64() {
+                        index.insert(asn as u32, IndexEntry { offset, length });
+                    }
+                }
+                offset += length;
+            }
+            Err(e) => {
+                error!("failed to serialize AS info: {}", e);
+                return Err(1);
+            }
+        }
     }
+    Ok(index)
+}
+
+/// Write `values` as a single MessagePack array of maps.
+fn write_msgpack(writer: &mut impl Write, values: impl Iterator<Item = Value>) -> Result<(), i32> {
+    let values: Vec<Value> = values.collect();
+    rmp_serde::encode::write(writer, &values).map_err(|e| {
+        error!("failed to write MessagePack array: {}", e);
+        1
+    })
 }
 
-fn generate_cmd(path: &str, simplified_flag: bool) -> Result<(), i32> {
-    let format: ExportFormat = if path.contains(".jsonl") {
+/// Convert records into a struct-of-arrays object keyed by field name.
+///
+/// Every array has one entry per record at the same index; a field missing from a record is
+/// `null` at that index.
+fn to_columnar(records: Vec<Value>) -> Value {
+    let mut columns: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    let total = records.len();
+    for (i, record) in records.into_iter().enumerate() {
+        let Value::Object(fields) = record else {
+            continue;
+        };
+        for (key, value) in fields {
+            let column = columns
+                .entry(key)
+                .or_insert_with(|| vec![Value::Null; total]);
+            column[i] = value;
+        }
+    }
+    json!(columns)
+}
+
+/// Byte range of a single record in a JSONL export.
+#[derive(Serialize)]
+struct IndexEntry {
+    offset: u64,
+    length: u64,
+}
+
+fn write_index(path: &str, index: &BTreeMap<u32, IndexEntry>) -> Result<String, i32> {
+    let index_path = format!("{path}.index.json");
+    let mut writer = oneio::get_writer(&index_path).map_err(|e| {
+        error!(
+            "failed to open index writer for path '{}': {}",
+            index_path, e
+        );
+        1
+    })?;
+    let s = serde_json::to_string(index).map_err(|e| {
+        error!("failed to serialize index: {}", e);
+        1
+    })?;
+    if writeln!(writer, "{}", s).is_err() {
+        error!("failed to write index file");
+        return Err(1);
+    }
+    info!("wrote index of {} records to '{}'", index.len(), index_path);
+    Ok(index_path)
+}
+
+/// Format of a `generate` export: `--format` if given, otherwise inferred from `path`. Postgres
+/// URLs always export to Postgres, and stdout (`-`) defaults to JSONL.
+/// Write `rows` to `path` with one of the writers that need a real file: Parquet, Avro, or SQLite.
+fn write_table(
+    path: &str,
+    format: ExportFormat,
+    rows: &[AsInfoSimplified],
+    compact_country: bool,
+) -> Result<(), i32> {
+    match format {
+        ExportFormat::Parquet => parquet_export::write_parquet(path, rows, compact_country),
+        ExportFormat::Avro => avro_export::write_avro(path, rows, compact_country),
+        _ => sqlite_export::write_sqlite(path, rows, compact_country),
+    }
+}
+
+/// Size of the `--dry-run` output for the [`write_table`] formats, measured by writing a file to
+/// the temp directory and removing it again.
+fn dry_run_table_size(
+    format: ExportFormat,
+    rows: &[AsInfoSimplified],
+    compact_country: bool,
+) -> Result<u64, i32> {
+    let path = std::env::temp_dir()
+        .join(format!("asninfo-dry-run-{}.{format}", std::process::id()))
+        .to_string_lossy()
+        .into_owned();
+    let written = write_table(&path, format, rows, compact_country);
+    let size = std::fs::metadata(&path).map(|m| m.len());
+    let _ = std::fs::remove_file(&path);
+    written?;
+    size.map_err(|e| {
+        error!("failed to read the size of the dry run {format} output: {e}");
+        1
+    })
+}
+
+fn export_format(path: &str, format: Option<&str>) -> Result<ExportFormat, i32> {
+    let to_stdout = path == STDOUT_PATH;
+    let format: ExportFormat = if postgres::is_postgres_url(path) {
+        ExportFormat::Postgres
+    } else if let Some(name) = format {
+        let Some(format) = ExportFormat::from_name(name) else {
+            error!(
+                "unknown format '{name}', choose from csv, json, jsonl, parquet, sqlite, msgpack, avro"
+            );
+            return Err(1);
+        };
+        format
+    } else if to_stdout {
         ExportFormat::JSONL
-    } else if path.contains(".csv") {
-        ExportFormat::CSV
-    } else if path.contains(".json") {
-        ExportFormat::JSON
+    } else if let Some(format) = ExportFormat::from_path(path) {
+        format
     } else {
-        error!("unknown format. please choose from csv, json, jsonl format");
+        error!(
+            "unknown format. please choose from csv, json, jsonl, parquet, sqlite, msgpack, avro format, or set --format"
+        );
         return Err(1);
     };
+    if to_stdout && !format.uses_writer() {
+        error!("{format} exports cannot be written to stdout, give a file path instead");
+        return Err(1);
+    }
+    Ok(format)
+}
 
-    let simplified = simplified_flag || matches!(format, ExportFormat::CSV);
+fn generate_cmd(path: &str, opts: &GenerateOptions) -> Result<(), i32> {
+    let started = Instant::now();
+    let to_stdout = path == STDOUT_PATH;
+    let format = export_format(path, opts.format.as_deref())?;
+    let self_contained = matches!(
+        format,
+        ExportFormat::Parquet | ExportFormat::Sqlite | ExportFormat::Avro
+    );
+    if self_contained && is_compressed_path(path) {
+        error!(
+            "{format} files cannot be compressed externally, remove the compression suffix from '{path}'"
+        );
+        return Err(1);
+    }
 
-    let load_population = !simplified;
-    let load_hegemony = !simplified;
-    let load_peeringdb = !simplified;
+    // the legacy schema only needs the simplified datasets
+    let tabular = matches!(
+        format,
+        ExportFormat::CSV | ExportFormat::Parquet | ExportFormat::Sqlite | ExportFormat::Avro
+    );
+    let simplified = opts.simplified || opts.legacy || tabular;
+    if opts.legacy && tabular {
+        warn!(
+            "--legacy has no effect on {format} exports, which already use the simplified schema"
+        );
+    }
+    // byte offsets index the file as written, so a compressed JSONL file cannot be indexed
+    let emit_index = opts.emit_index
+        && matches!(format, ExportFormat::JSONL)
+        && !to_stdout
+        && !is_compressed_path(path);
+    if opts.emit_index && !emit_index {
+        if is_compressed_path(path) {
+            warn!("--emit-index ignored, '{}' is compressed", path);
+        } else {
+            warn!("--emit-index is only supported for JSONL file exports, skipping index");
+        }
+    }
+    if opts.validate_schema.is_some() && tabular {
+        warn!("--validate-schema is only supported for JSON/JSONL exports, skipping validation");
+    }
+    let columnar = opts.columnar && matches!(format, ExportFormat::JSON);
+    if opts.columnar && !columnar {
+        warn!("--columnar is only supported for JSON exports, writing records as rows");
+    }
+    let pretty = opts.pretty && matches!(format, ExportFormat::JSON);
+    if opts.pretty && !pretty {
+        warn!("--pretty is only supported for JSON exports, ignoring it for {format} output");
+    }
+    if opts.bom && !matches!(format, ExportFormat::CSV) {
+        warn!("--bom is only supported for CSV exports, writing without a byte order mark");
+    }
+    if opts.full_org
+        && matches!(
+            format,
+            ExportFormat::Parquet
+                | ExportFormat::Sqlite
+                | ExportFormat::Avro
+                | ExportFormat::Postgres
+        )
+    {
+        warn!("--full-org is not supported for {format} exports, writing the standard columns");
+    } else if opts.full_org && (!simplified || opts.legacy) {
+        warn!("--full-org only applies to simplified output, the full schema includes all as2org fields");
+    }
+    if !opts.columns.is_empty() && !matches!(format, ExportFormat::CSV) {
+        warn!("--columns is only supported for CSV exports, writing all fields");
+    }
+    let csv_columns = csv_columns(&opts.columns, opts.compact_country, opts.full_org)?;
+    if let Some(score) = opts.min_hegemony {
+        if !score.is_finite() || score < 0.0 {
+            error!("--min-hegemony must be a non-negative number, got {score}");
+            return Err(1);
+        }
+        if simplified {
+            error!(
+                "--min-hegemony needs the hegemony dataset, which is not loaded for simplified, \
+                 legacy, or {format} exports"
+            );
+            return Err(1);
+        }
+        if !opts.manifest.hegemony.enabled {
+            error!("--min-hegemony needs the hegemony dataset, which is disabled");
+            return Err(1);
+        }
+    }
 
-    info!("loading asn info data ...");
-    let mut commons = bgpkit_commons::BgpkitCommons::new();
-    if let Err(e) = commons.load_asinfo(true, load_population, load_hegemony, load_peeringdb) {
-        error!("failed to load asn info data: {e}");
-        return Err(1);
-    };
-    if let Err(e) = commons.load_countries() {
-        error!("failed to load countries: {e}");
-        return Err(2);
+    let mut manifest = opts.manifest.clone();
+    if opts.compact_country || opts.from_file.is_some() {
+        manifest.countries.enabled = false;
+    }
+    // the netixlan download does not depend on bgpkit-commons, so it runs alongside it
+    let load_records = || -> Result<_, i32> {
+        match &opts.from_file {
+            // offline: nothing is loaded into commons, so country lookups come back empty
+            Some(file) => Ok((BgpkitCommons::new(), load_snapshot(file)?)),
+            None => load_commons(&manifest, simplified, &opts.retry),
+        }
     };
-    let as_info_map = commons.asinfo_all().expect("failed to get asinfo map");
+    let load_ix = opts.at_ix || opts.with_ix;
+    let ((commons, as_info_map), ix_presence) =
+        load_concurrently(load_records, load_ix.then_some(peeringdb::load_ix_presence))?;
+    if opts.require_all_datasets {
+        let skipped = skipped_datasets(&commons, &as_info_map, &manifest, simplified);
+        if !skipped.is_empty() {
+            error!(
+                "required datasets were skipped or failed to load: {}",
+                skipped.join(", ")
+            );
+            return Err(DATASET_SKIPPED_EXIT_CODE);
+        }
+    }
+    check_dataset_size(as_info_map.len(), opts)?;
 
     info!("export format: {}", &format);
 
-    info!("writing asn info data to '{}' ...", &path);
-    let mut writer = match oneio::get_writer(&path) {
-        Ok(w) => w,
-        Err(e) => {
-            error!("failed to open writer for path '{}': {}", path, e);
-            return Err(1);
+    let written_bytes = Arc::new(AtomicU64::new(0));
+    let mut writer: Box<dyn Write> = if !format.uses_writer() {
+        // records are written to the destination by a dedicated exporter
+        Box::new(std::io::sink())
+    } else if opts.dry_run {
+        info!("dry run: serializing records without writing '{path}'");
+        Box::new(CountingWriter(written_bytes.clone()))
+    } else if to_stdout {
+        info!("writing asn info data to stdout ...");
+        Box::new(std::io::BufWriter::new(std::io::stdout().lock()))
+    } else {
+        info!("writing asn info data to '{}' ...", &path);
+        match oneio::get_writer(path) {
+            Ok(w) => w,
+            Err(e) => {
+                error!("failed to open writer for path '{}': {}", path, e);
+                return Err(1);
+            }
         }
     };
+    let gzip_path = format!("{path}.gz");
+    let write_gzip = opts.also_gzip
+        && !is_compressed_path(path)
+        && format.uses_writer()
+        && !opts.dry_run
+        && !to_stdout;
+    if opts.also_gzip {
+        if opts.dry_run {
+            info!("dry run: skipping --also-gzip");
+        } else if to_stdout {
+            warn!("--also-gzip ignored when writing to stdout");
+        } else if !format.uses_writer() {
+            warn!("--also-gzip ignored for {format} exports");
+        } else if !write_gzip {
+            warn!("--also-gzip ignored, '{}' is already compressed", path);
+        } else {
+            info!("also writing gzipped copy to '{}' ...", &gzip_path);
+            let gz_writer = match oneio::get_writer(&gzip_path) {
+                Ok(w) => w,
+                Err(e) => {
+                    error!("failed to open writer for path '{}': {}", gzip_path, e);
+                    return Err(1);
+                }
+            };
+            writer = Box::new(TeeWriter(writer, gz_writer));
+        }
+    }
     let mut info_vec = as_info_map.values().collect::<Vec<_>>();
-    info_vec.sort_by(|a, b| a.asn.cmp(&b.asn));
+    if !opts.only_asns.is_empty() {
+        let only_asns: HashSet<u32> = opts.only_asns.iter().copied().collect();
+        info_vec.retain(|v| only_asns.contains(&v.asn));
+        info!(
+            "filtered to {} of {} requested ASNs",
+            info_vec.len(),
+            only_asns.len()
+        );
+    }
+    if let Some(filter) = &opts.filter {
+        let before = info_vec.len();
+        info_vec.retain(|v| filter.matches(v));
+        info!("filter matched {} of {} records", info_vec.len(), before);
+    }
+    if let Some(score) = opts.min_hegemony {
+        let before = info_vec.len();
+        // full-schema records carry the `hegemony` object, showing why each record was kept
+        info_vec.retain(|v| {
+            v.hegemony
+                .as_ref()
+                .is_some_and(|h| h.ipv4.max(h.ipv6) >= score)
+        });
+        info!(
+            "{} of {} records have a hegemony score of at least {score}",
+            info_vec.len(),
+            before
+        );
+    }
+    if !opts.rir.is_empty() {
+        let rirs = rir::AsnRirs::load()?;
+        let before = info_vec.len();
+        info_vec.retain(|v| rirs.rir(v.asn).is_some_and(|r| opts.rir.contains(&r)));
+        let names: Vec<String> = opts.rir.iter().map(|r| r.to_string()).collect();
+        info!(
+            "{} of {} records are delegated by {}",
+            info_vec.len(),
+            before,
+            names.join(", ")
+        );
+    }
+    if let (true, Some(presence)) = (opts.at_ix, &ix_presence) {
+        info_vec.retain(|v| presence.get(&v.asn).is_some_and(|ixs| !ixs.is_empty()));
+        info!("filtered to {} ASNs present at an IX", info_vec.len());
+    }
+    if let Some(sample) = opts.sample {
+        let total = info_vec.len();
+        // start from ASN order so a seeded sample does not depend on hash map iteration order
+        info_vec.sort_by_key(|v| v.asn);
+        let mut rng = match opts.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        info_vec.shuffle(&mut rng);
+        info_vec.truncate(sample.count_of(total));
+        info!("sampled {} of {} records", info_vec.len(), total);
+    }
+    info_vec.sort_by(|a, b| opts.sort_by.compare(a, b));
+    // after sorting, so the same records are kept on every run
+    if let Some(limit) = opts.limit.filter(|limit| *limit < info_vec.len()) {
+        info!("limited to the first {limit} of {} records", info_vec.len());
+        info_vec.truncate(limit);
+    }
+    let record_count = info_vec.len();
+    if opts.dry_run && opts.emit_errors_file.is_some() {
+        info!("dry run: skipping --emit-errors-file");
+    } else if let Some(errors_path) = &opts.emit_errors_file {
+        let checks = quality::QualityChecks {
+            countries: manifest.countries.enabled,
+            as2org: manifest.as2org.enabled,
+            verbose: opts.errors_verbose,
+        };
+        quality::write_errors_file(errors_path, &info_vec, &commons, &checks)?;
+    }
+
+    // sidecar files to upload next to the data file, as (local path, key suffix)
+    let mut sidecars: Vec<(String, &str)> = Vec::new();
 
     match format {
-        ExportFormat::JSON | ExportFormat::JSONL => {
-            let values_vec: Vec<Value> = match simplified {
-                false => info_vec.into_iter().map(|v| json!(v)).collect(),
-                true => info_vec
-                    .into_iter()
-                    .map(|v| {
-                        let mut info = AsInfoSimplified::from(v);
-                        if let Ok(Some(name)) = commons.country_by_code(&info.country_code) {
-                            info.country_name = name.name
+        ExportFormat::JSON
+        | ExportFormat::JSONL
+        | ExportFormat::MsgPack
+        | ExportFormat::Postgres => {
+            let to_value = |v: &AsInfo| -> Value {
+                if opts.legacy {
+                    let country_name = commons
+                        .country_by_code(&v.country)
+                        .ok()
+                        .flatten()
+                        .map(|c| c.name);
+                    return legacy_record(v, country_name.as_deref());
+                }
+                if simplified {
+                    let mut info = AsInfoSimplified::from(v);
+                    if !opts.full_org {
+                        info = info.without_full_org();
+                    }
+                    info.note = opts.annotations.get(&v.asn).cloned();
+                    if let Ok(Some(name)) = commons.country_by_code(&info.country_code) {
+                        info.country_name = Some(name.name)
+                    }
+                    let mut value = json!(info);
+                    if opts.compact_country {
+                        if let Some(obj) = value.as_object_mut() {
+                            obj.remove("country_name");
                         }
-                        json!(info)
-                    })
-                    .collect(),
+                    }
+                    return value;
+                }
+                // same record shape as the API; country data is not loaded with --compact-country
+                let ixs = peeringdb::ix_list(ix_presence.as_ref(), v.asn);
+                json!(AsInfoOut {
+                    inner: v.clone(),
+                    country_name: commons
+                        .country_by_code(&v.country)
+                        .ok()
+                        .flatten()
+                        .map(|c| c.name),
+                    continent: None,
+                    countries: None,
+                    ix_count: ixs.as_ref().map(|ixs| ixs.len()),
+                    ixs,
+                    note: opts.annotations.get(&v.asn).cloned(),
+                    data_source: data_source(v),
+                })
             };
-            if matches!(format, ExportFormat::JSONL) {
-                for as_info in values_vec {
-                    match serde_json::to_string(&as_info) {
-                        Ok(s) => {
-                            if writeln!(writer, "{}", s).is_err() {
-                                error!("failed to write to file");
-                                return Err(1);
-                            }
-                        }
-                        Err(e) => {
-                            error!("failed to serialize AS info: {}", e);
-                            return Err(1);
-                        }
+
+            // schema validation, postgres, and the columnar layout need every record up front;
+            // otherwise records are serialized and written one at a time
+            let buffered = opts.validate_schema.is_some()
+                || columnar
+                || matches!(format, ExportFormat::Postgres);
+            let values: Box<dyn Iterator<Item = Value> + '_> = if buffered {
+                let values_vec: Vec<Value> = info_vec.into_iter().map(to_value).collect();
+                if let Some(schema_path) = &opts.validate_schema {
+                    schema::validate_records(schema_path, &values_vec)?;
+                }
+                if matches!(format, ExportFormat::Postgres) {
+                    if opts.dry_run {
+                        info!("dry run: {record_count} records, nothing loaded into postgres");
+                        return Ok(());
                     }
+                    postgres::copy_records(path, &opts.pg_table, &values_vec, simplified)?;
+                    info!("asninfo export to postgres done");
+                    return Ok(());
                 }
+                Box::new(values_vec.into_iter())
             } else {
-                match serde_json::to_string(&values_vec) {
+                Box::new(info_vec.into_iter().map(to_value))
+            };
+
+            if matches!(format, ExportFormat::MsgPack) {
+                write_msgpack(&mut writer, values)?;
+            } else if matches!(format, ExportFormat::JSONL) {
+                let index = write_jsonl(&mut writer, values, emit_index)?;
+                if emit_index && !opts.dry_run {
+                    sidecars.push((write_index(path, &index)?, ".index.json"));
+                }
+            } else if columnar {
+                let output = to_columnar(values.collect());
+                let serialized = match pretty {
+                    true => serde_json::to_string_pretty(&output),
+                    false => serde_json::to_string(&output),
+                };
+                match serialized {
                     Ok(s) => {
                         if writeln!(writer, "{}", s).is_err() {
                             error!("failed to write to file");
@@ -231,92 +1710,469 @@ fn generate_cmd(path: &str, simplified_flag: bool) -> Result<(), i32> {
                         return Err(1);
                     }
                 }
+            } else {
+                write_json_array(&mut writer, values, pretty)?;
             }
         }
         ExportFormat::CSV => {
-            writeln!(
-                writer,
-                "asn,as_name,org_id,org_name,country_code,country_name,data_source"
-            )
-            .unwrap();
-            for asninfo in info_vec {
+            let rows = info_vec.into_iter().map(|asninfo| {
                 let mut info = AsInfoSimplified::from(asninfo);
-                if let Ok(Some(name)) = commons.country_by_code(&info.country_code) {
-                    info.country_name = name.name
+                if !opts.full_org {
+                    info = info.without_full_org();
                 }
-                writeln!(
-                    writer,
-                    r#"{},"{}","{}","{}","{}","{}","""#,
-                    info.asn,
-                    info.as_name.replace('"', ""),
-                    info.org_id,
-                    info.org_name.replace('"', ""),
-                    info.country_code,
-                    info.country_name
-                )
-                .unwrap();
+                if !opts.compact_country {
+                    if let Ok(Some(name)) = commons.country_by_code(&info.country_code) {
+                        info.country_name = Some(name.name)
+                    }
+                }
+                info
+            });
+            write_csv(&mut writer, &csv_columns, rows, opts.bom)?;
+        }
+        ExportFormat::Parquet | ExportFormat::Sqlite | ExportFormat::Avro => {
+            let rows: Vec<AsInfoSimplified> = info_vec
+                .into_iter()
+                .map(|v| {
+                    let mut info = AsInfoSimplified::from(v).without_full_org();
+                    if !opts.compact_country {
+                        if let Ok(Some(country)) = commons.country_by_code(&info.country_code) {
+                            info.country_name = Some(country.name)
+                        }
+                    }
+                    info
+                })
+                .collect();
+            if opts.dry_run {
+                let size = dry_run_table_size(format, &rows, opts.compact_country)?;
+                info!(
+                    "dry run: {record_count} records, {size} bytes of {format} output; nothing \
+                     written or uploaded"
+                );
+                return Ok(());
             }
+            write_table(path, format, &rows, opts.compact_country)?;
         }
     }
+    if writer.flush().is_err() {
+        error!("failed to write to file");
+        return Err(1);
+    }
     drop(writer);
+    if opts.dry_run {
+        let compression = match split_compression_suffix(path) {
+            (_, Some(suffix)) => format!(", before {suffix} compression"),
+            (_, None) => String::new(),
+        };
+        info!(
+            "dry run: {record_count} records, {} bytes of {format} output{compression}; nothing \
+             written or uploaded",
+            written_bytes.load(Ordering::Relaxed)
+        );
+        return Ok(());
+    }
+    // stdout output is not a file, so there is nothing to checksum, sign, or upload
+    if to_stdout {
+        info!("asninfo export to stdout done, {record_count} records");
+        return Ok(());
+    }
+    if let (_, Some(suffix)) = split_compression_suffix(path) {
+        if let Ok(meta) = std::fs::metadata(path) {
+            info!(
+                "wrote {} bytes ({suffix} compressed) to '{path}'",
+                meta.len()
+            );
+        }
+    }
+    if write_gzip {
+        sidecars.push((gzip_path, ".gz"));
+    }
+
+    // the .sha256 sidecar is uploaded per destination, naming that destination's key
+    let mut digest = None;
+    if !opts.no_checksum {
+        let (_, hex) = checksum::write_checksum(path)?;
+        digest = Some(hex);
+    }
+
+    if let Ok(raw_key) = dotenvy::var("ASNINFO_SIGNING_KEY") {
+        sidecars.push((signing::sign_file(path, &raw_key)?, ".sig"));
+    }
 
-    if let Ok(upload_path) = std::env::var("ASNINFO_UPLOAD_PATH") {
-        info!("uploading {} to {} ...", &path, upload_path);
+    let mut destinations = upload_destinations();
+    if opts.limit.is_some() && !destinations.is_empty() {
+        warn!("--limit truncates the export, skipping upload");
+        destinations.clear();
+    }
+    if !destinations.is_empty() {
         if oneio::s3_env_check().is_err() {
             error!("S3 environment variables not set, skipping upload");
             return Err(3);
-        } else {
-            let (bucket, key) = oneio::s3_url_parse(&upload_path).unwrap();
-            match oneio::s3_upload(&bucket, &key, &path) {
-                Ok(_) => {
-                    // try to do send a success message to
-                    if let Ok(raw_url) = dotenvy::var("ASNINFO_HEARTBEAT_URL") {
-                        let heartbeat_url = sanitize_url(&raw_url);
-                        if heartbeat_url.is_empty() {
-                            error!("heartbeat URL is empty after sanitization");
-                            return Err(4);
-                        }
-                        info!("sending heartbeat to configured URL");
-                        if let Err(e) = oneio::read_to_string(&heartbeat_url) {
-                            error!("failed to send heartbeat: {e}");
-                            return Err(4);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("failed to upload to destination ({upload_path}): {e}");
-                    return Err(5);
+        }
+        // try every destination so one unreachable bucket does not block the others
+        let mut uploaded = 0;
+        let mut failed = 0;
+        for upload_path in &destinations {
+            let Ok((bucket, key)) = upload_key(path, upload_path) else {
+                failed += 1;
+                continue;
+            };
+            if let (Some(digest), false) = (&digest, opts.force_upload) {
+                if remote_unchanged(&bucket, &key, upload_path, digest) {
+                    info!(
+                        "content unchanged, skipping upload. {upload_path} already has sha256 \
+                         {digest}"
+                    );
+                    continue;
                 }
             }
+            let mut destination_sidecars = sidecars.clone();
+            let mut upload_checksum = None;
+            if let Some(digest) = &digest {
+                let Ok(checksum_path) = checksum::write_upload_checksum(digest, &key) else {
+                    failed += 1;
+                    continue;
+                };
+                destination_sidecars.push((checksum_path.clone(), ".sha256"));
+                upload_checksum = Some(checksum_path);
+            }
+            let result = upload_file(path, upload_path, (&bucket, &key), &destination_sidecars);
+            if let Some(checksum_path) = upload_checksum {
+                let _ = std::fs::remove_file(checksum_path);
+            }
+            match result {
+                Ok(()) => uploaded += 1,
+                Err(_) => failed += 1,
+            }
         }
+        if failed > 0 {
+            error!(
+                "upload failed for {failed} of {} destinations",
+                destinations.len()
+            );
+            return Err(5);
+        }
+        // try to do send a success message to
+        if let Ok(raw_url) = dotenvy::var("ASNINFO_HEARTBEAT_URL") {
+            let heartbeat_url = sanitize_url(&raw_url);
+            if heartbeat_url.is_empty() {
+                error!("heartbeat URL is empty after sanitization");
+                return Err(4);
+            }
+            let hb = heartbeat::HeartbeatInfo {
+                status: if uploaded == 0 { "unchanged" } else { "ok" },
+                record_count,
+                elapsed: started.elapsed(),
+                path,
+            };
+            if let Err(e) = heartbeat::send_heartbeat(&heartbeat_url, &hb) {
+                error!("failed to send heartbeat: {e}");
+                return Err(4);
+            }
+        }
+    }
+    match digest {
+        Some(digest) => info!("asninfo download done (sha256 {digest})"),
+        None => info!("asninfo download done"),
     }
-    info!("asninfo download done");
     Ok(())
 }
 
-async fn serve_cmd(bind: &str, refresh_secs: u64, simplified: bool) -> Result<(), i32> {
-    let (initial_map, updated_at_str) = load_asn_map_out(simplified)?;
-    let map = Arc::new(Mutex::new(initial_map));
+/// Upload destinations from `ASNINFO_UPLOAD_PATH`, a comma-separated list of S3/R2 URLs.
+fn upload_destinations() -> Vec<String> {
+    std::env::var("ASNINFO_UPLOAD_PATH")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Bucket and object key `path` is uploaded to at `upload_path`.
+fn upload_key(path: &str, upload_path: &str) -> Result<(String, String), i32> {
+    let (bucket, mut key) = oneio::s3_url_parse(upload_path).map_err(|e| {
+        error!("invalid upload destination ({upload_path}): {e}");
+        5
+    })?;
+    // keep the compression suffix so consumers can tell how to decode the object
+    if let (_, Some(suffix)) = split_compression_suffix(path) {
+        if !key.ends_with(suffix) {
+            key.push_str(suffix);
+            info!("upload key adjusted to '{key}' to keep the '{suffix}' suffix");
+        }
+    }
+    Ok((bucket, key))
+}
+
+/// Whether the object at `bucket`/`key` already has the content hashed to `digest`, according to
+/// the `.sha256` sidecar uploaded next to it.
+///
+/// A missing or unreadable sidecar counts as changed, so the file is uploaded.
+fn remote_unchanged(bucket: &str, key: &str, upload_path: &str, digest: &str) -> bool {
+    let remote = oneio::s3_reader(bucket, &format!("{key}.sha256"))
+        .map_err(|e| e.to_string())
+        .and_then(|mut reader| {
+            let mut content = String::new();
+            reader
+                .read_to_string(&mut content)
+                .map(|_| content)
+                .map_err(|e| e.to_string())
+        });
+    match remote {
+        Ok(content) => content.split_whitespace().next() == Some(digest),
+        Err(e) => {
+            info!("no readable checksum at {upload_path}.sha256 ({e}), uploading");
+            false
+        }
+    }
+}
+
+/// Upload `path` and its sidecars (local path and key suffix) to one S3/R2 destination, at the
+/// bucket and key from [`upload_key`].
+fn upload_file(
+    path: &str,
+    upload_path: &str,
+    (bucket, key): (&str, &str),
+    sidecars: &[(String, &str)],
+) -> Result<(), i32> {
+    info!("uploading {} to {} ...", path, upload_path);
+    if let Err(e) = oneio::s3_upload(bucket, key, path) {
+        error!("failed to upload to destination ({upload_path}): {e}");
+        return Err(5);
+    }
+    for (sidecar_path, suffix) in sidecars {
+        let sidecar_key = format!("{key}{suffix}");
+        info!(
+            "uploading {} to {}{} ...",
+            sidecar_path, upload_path, suffix
+        );
+        if let Err(e) = oneio::s3_upload(bucket, &sidecar_key, sidecar_path) {
+            error!("failed to upload to destination ({upload_path}{suffix}): {e}");
+            return Err(5);
+        }
+    }
+    info!("uploaded {path} to {upload_path}");
+    Ok(())
+}
+
+/// Run `generate` every `refresh_secs` seconds until the process is stopped.
+///
+/// Failed runs are logged and retried on the next cycle rather than ending the daemon.
+async fn daemon_cmd(path: String, refresh_secs: u64, opts: GenerateOptions) {
+    let interval = Duration::from_secs(refresh_secs.max(MINIMUM_UPDATER_INTERVAL_SECS));
+    let opts = Arc::new(opts);
+    loop {
+        let (run_path, run_opts) = (path.clone(), opts.clone());
+        let result = tokio::task::spawn_blocking(move || generate_cmd(&run_path, &run_opts))
+            .await
+            .unwrap_or(Err(1));
+        match result {
+            Ok(()) => info!(
+                "daemon: export finished, next run in {}s",
+                interval.as_secs()
+            ),
+            Err(code) => error!(
+                "daemon: export failed with code {code}, retrying in {}s",
+                interval.as_secs()
+            ),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Options for the `serve` subcommand besides the bind address and dataset loading.
+struct ServeOptions {
+    refresh_secs: u64,
+    entry_ttl_secs: Option<u64>,
+    annotations_path: Option<String>,
+    with_pfx2as: bool,
+    upstream_probe: Option<Arc<UpstreamProbe>>,
+    failure_policy: RefreshFailurePolicy,
+}
+
+async fn serve_cmd(bind: &str, opts: LoadOptions, serve_opts: ServeOptions) -> Result<(), i32> {
+    let ServeOptions {
+        refresh_secs,
+        entry_ttl_secs,
+        annotations_path,
+        with_pfx2as,
+        upstream_probe,
+        failure_policy,
+    } = serve_opts;
+    // refresh at least as often as the shortest dataset cache TTL
+    let refresh_secs = opts
+        .manifest
+        .min_cache_ttl_secs()
+        .map_or(refresh_secs, |ttl| refresh_secs.min(ttl));
+    let refresh_interval = Duration::from_secs(refresh_secs.max(MINIMUM_UPDATER_INTERVAL_SECS));
+    let cached = match (&opts.cache_dir, &opts.from_file) {
+        (Some(dir), None) => cache::load(dir, &opts, refresh_interval),
+        _ => None,
+    };
+    let (((mut initial_map, updated_at_str, failed_datasets), pfx2as), data_age) = match cached {
+        // prefix-to-origin data is not cached
+        Some((records, age)) => ((records, with_pfx2as.then(Pfx2As::load).transpose()?), age),
+        None => {
+            let loaded = load_concurrently(
+                || load_asn_map_out(&opts, true),
+                with_pfx2as.then_some(Pfx2As::load),
+            )?;
+            (loaded, Duration::ZERO)
+        }
+    };
+    let pfx2as = pfx2as.map(|p| Arc::new(Mutex::new(p)));
+    let initial_annotations = match &annotations_path {
+        Some(path) => annotations::load_annotations(path)?,
+        None => Annotations::default(),
+    };
+    annotations::apply_annotations(&mut initial_map, &initial_annotations);
+    let org_index = Arc::new(Mutex::new(build_org_index(&initial_map)));
+    let org_id_index = Arc::new(Mutex::new(build_org_id_index(&initial_map)));
+    let etag = Arc::new(Mutex::new(dataset_etag(&initial_map)));
+    let datasets = Arc::new(Mutex::new(dataset_status(
+        &initial_map,
+        &opts,
+        &failed_datasets,
+    )));
+    let map = Arc::new(Mutex::new(RecordMap::new(
+        initial_map,
+        opts.compress_records,
+    )));
     let updated_at = Arc::new(Mutex::new(updated_at_str));
+    let annotations = Arc::new(Mutex::new(initial_annotations));
 
     // config: max ASNs per request (default 100)
     let max_asns: usize = dotenvy::var("ASNINFO_MAX_ASNS")
         .ok()
         .and_then(|s| s.parse::<usize>().ok())
         .unwrap_or(100);
+    // config: highest per-request `limit` (default: max_asns, i.e. no raising)
+    let max_asns_ceiling: usize = dotenvy::var("ASNINFO_MAX_ASNS_CEILING")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .map_or(max_asns, |ceiling| {
+            if ceiling < max_asns {
+                warn!(
+                    "ASNINFO_MAX_ASNS_CEILING ({ceiling}) is below ASNINFO_MAX_ASNS ({max_asns}), \
+                     using {max_asns}"
+                );
+            }
+            ceiling.max(max_asns)
+        });
+
+    // config: bearer token for all endpoints except /health (default: open)
+    let api_token: Option<Arc<str>> = dotenvy::var("ASNINFO_API_TOKEN")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .map(Arc::from);
+    if api_token.is_some() {
+        info!("bearer token authentication enabled for all endpoints except /health");
+    }
+
+    // config: requests per minute per client IP (default: unlimited)
+    let rate_limiter = dotenvy::var("ASNINFO_RATE_LIMIT")
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|n| *n > 0)
+        .map(|n| {
+            info!("rate limiting clients to {n} requests per minute");
+            Arc::new(ratelimit::RateLimiter::new(n))
+        });
+
+    // config: concurrent large-batch renders (default: available CPUs)
+    let batch_concurrency: usize = dotenvy::var("ASNINFO_BATCH_CONCURRENCY")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
 
     let state = AppState {
         map: map.clone(),
-        updated_at: updated_at.clone(),
+        org_index,
+        org_id_index,
+        annotations,
+        etag,
+        datasets,
+        previous: Arc::new(Mutex::new(HashMap::new())),
+        updated_at,
         max_asns,
+        max_asns_ceiling,
+        with_ix: opts.with_ix,
+        batch_permits: Arc::new(Semaphore::new(batch_concurrency)),
+        refresh_hint: entry_ttl_secs.map(|_| Arc::new(Notify::new())),
+        pfx2as,
+        upstream_probe,
+        metrics: Arc::new(metrics::Metrics::new()),
+        next_refresh: Arc::new(Mutex::new(None)),
+        api_token,
+        rate_limiter,
+        refresh_status: Arc::new(Mutex::new(RefreshStatus::default())),
+        records_writer: Arc::new(tokio::sync::Mutex::new(())),
     };
 
     // start background updater
-    let _handle = start_updater(map.clone(), updated_at.clone(), refresh_secs, simplified);
+    let updater = start_updater(
+        state.clone(),
+        refresh_secs,
+        entry_ttl_secs,
+        opts,
+        failure_policy,
+        data_age,
+    );
+    let annotations_watcher =
+        annotations_path.map(|path| annotations::start_annotations_watcher(path, state.clone()));
 
     // build API router
     let app = build_router(state);
 
+    let result = match bind.strip_prefix("unix:") {
+        Some(socket_path) => serve_unix(socket_path, app).await,
+        None => serve_tcp(bind, app).await,
+    };
+
+    // in-flight requests are drained; stop the background tasks so the process exits promptly
+    updater.abort();
+    if let Some(watcher) = annotations_watcher {
+        watcher.abort();
+    }
+    result
+}
+
+/// Resolve once SIGINT or (on unix) SIGTERM is received.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("failed to listen for SIGINT: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("failed to listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("received shutdown signal, draining connections");
+}
+
+async fn serve_tcp(bind: &str, app: axum::Router) -> Result<(), i32> {
     let addr: SocketAddr = bind.parse().map_err(|e| {
         error!("invalid bind address {bind}: {e}");
         6
@@ -326,10 +2182,504 @@ async fn serve_cmd(bind: &str, refresh_secs: u64, simplified: bool) -> Result<()
         6
     })?;
     info!("serving on http://{}", addr);
-    axum::serve(listener, app).await.map_err(|e| {
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .map_err(|e| {
         error!("server error: {e}");
         7
     })?;
 
     Ok(())
 }
+
+#[cfg(unix)]
+async fn serve_unix(socket_path: &str, app: axum::Router) -> Result<(), i32> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // remove a stale socket left behind by a previous run, but never another kind of file
+    if let Ok(metadata) = std::fs::symlink_metadata(socket_path) {
+        if !metadata.file_type().is_socket() {
+            error!(
+                "refusing to bind unix socket {socket_path}: the path exists and is not a socket"
+            );
+            return Err(6);
+        }
+        if let Err(e) = std::fs::remove_file(socket_path) {
+            error!("failed to remove existing socket {socket_path}: {e}");
+            return Err(6);
+        }
+    }
+    let listener = tokio::net::UnixListener::bind(socket_path).map_err(|e| {
+        error!("failed to bind unix socket {socket_path}: {e}");
+        6
+    })?;
+    info!("serving on unix:{}", socket_path);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .map_err(|e| {
+            error!("server error: {e}");
+            7
+        })?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn serve_unix(socket_path: &str, _app: axum::Router) -> Result<(), i32> {
+    error!("unix socket bind (unix:{socket_path}) is not supported on this platform");
+    Err(6)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bgpkit_commons::asinfo::As2orgInfo;
+
+    fn as_info(asn: u32, as2org: Option<As2orgInfo>) -> AsInfo {
+        AsInfo {
+            asn,
+            name: format!("AS{asn}-NAME"),
+            country: "US".to_string(),
+            as2org,
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        }
+    }
+
+    fn as2org(org_id: &str, org_name: &str) -> As2orgInfo {
+        As2orgInfo {
+            name: "AS-NAME".to_string(),
+            country: "US".to_string(),
+            org_id: org_id.to_string(),
+            org_name: org_name.to_string(),
+        }
+    }
+
+    /// Scratch directory for one test, removed when dropped.
+    struct ScratchDir(std::path::PathBuf);
+
+    impl ScratchDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("asninfo-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            ScratchDir(dir)
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.0.join(file).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Write `records` as a full-schema JSONL snapshot and return options that export it offline.
+    fn snapshot_options(dir: &ScratchDir, records: &[AsInfo]) -> GenerateOptions {
+        let snapshot = dir.path("snapshot.jsonl");
+        let lines: Vec<String> = records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect();
+        std::fs::write(&snapshot, lines.join("\n")).unwrap();
+        GenerateOptions {
+            from_file: Some(snapshot),
+            no_checksum: true,
+            ..Default::default()
+        }
+    }
+
+    /// ASNs of a JSONL export, in file order.
+    fn jsonl_asns(path: &str) -> Vec<u64> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                serde_json::from_str::<Value>(l).unwrap()["asn"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn simplified_record_uses_null_for_missing_values() {
+        let value = json!(AsInfoSimplified::from(&as_info(64496, None)));
+        assert_eq!(value["org_id"], Value::Null);
+        assert_eq!(value["org_name"], Value::Null);
+        assert_eq!(value["country_name"], Value::Null);
+        assert!(value.get("note").is_none());
+    }
+
+    fn json_array(values: &[Value], pretty: bool) -> String {
+        let mut out = Vec::new();
+        write_json_array(&mut out, values.iter().cloned(), pretty).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pretty_json_array_round_trips() {
+        let values = vec![
+            json!(AsInfoSimplified::from(&as_info(64496, None))),
+            json!({
+                "asn": 64497,
+                "as_name": "Multi\nline \"quoted\" name, ü",
+                "as2org": {"org_id": "ORG-1", "nested": [1, 2, {"x": null}]},
+            }),
+        ];
+        let pretty = json_array(&values, true);
+        assert!(pretty.contains("\n  {\n    \""));
+        assert_eq!(serde_json::from_str::<Vec<Value>>(&pretty).unwrap(), values);
+        assert_eq!(
+            pretty,
+            format!("{}\n", serde_json::to_string_pretty(&values).unwrap())
+        );
+
+        let compact = json_array(&values, false);
+        assert_eq!(
+            serde_json::from_str::<Vec<Value>>(&compact).unwrap(),
+            values
+        );
+        assert_eq!(
+            compact,
+            format!("{}\n", serde_json::to_string(&values).unwrap())
+        );
+    }
+
+    #[test]
+    fn empty_json_array_round_trips() {
+        for pretty in [true, false] {
+            let out = json_array(&[], pretty);
+            assert_eq!(out, "[]\n");
+            assert!(serde_json::from_str::<Vec<Value>>(&out).unwrap().is_empty());
+        }
+    }
+
+    fn csv_output(rows: Vec<AsInfoSimplified>, bom: bool) -> String {
+        let mut out = Vec::new();
+        write_csv(&mut out, &CSV_COLUMNS, rows.into_iter(), bom).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_reparses_org_names_with_commas_quotes_and_newlines() {
+        let org_name = "Acme, \"Widgets\"\nand Gadgets";
+        let record = as_info(64496, Some(as2org("ORG-1", org_name)));
+        let out = csv_output(vec![AsInfoSimplified::from(&record)], false);
+
+        let mut reader = csv::Reader::from_reader(out.as_bytes());
+        assert_eq!(reader.headers().unwrap(), CSV_COLUMNS.as_slice());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].len(), CSV_COLUMNS.len());
+        assert_eq!(&rows[0][0], "64496");
+        assert_eq!(&rows[0][1], "AS64496-NAME");
+        assert_eq!(&rows[0][2], "ORG-1");
+        // line breaks are folded to a space so each record stays on one line
+        assert_eq!(&rows[0][3], "Acme, \"Widgets\" and Gadgets");
+        assert_eq!(&rows[0][4], "US");
+        assert_eq!(&rows[0][5], "");
+    }
+
+    #[test]
+    fn csv_output_is_byte_exact() {
+        let mut record = as_info(
+            64496,
+            Some(as2org("ORG-1", "Acme\r\nWidgets\rand\nGadgets")),
+        );
+        record.name = "Name, \"quoted\"".to_string();
+        let rows = || vec![AsInfoSimplified::from(&record)];
+        let expected = "asn,as_name,org_id,org_name,country_code,country_name,data_source\n\
+            64496,\"Name, \"\"quoted\"\"\",ORG-1,Acme Widgets and Gadgets,US,,\"ripe,caida\"\n";
+        assert_eq!(csv_output(rows(), false), expected);
+        // the byte order mark comes before the header and nothing else changes
+        assert_eq!(
+            csv_output(rows(), true).as_bytes(),
+            [b"\xef\xbb\xbf".as_slice(), expected.as_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn jsonl_output_is_byte_exact() {
+        let values = vec![
+            json!({"asn": 64496, "as_name": "Line\r\nbreak"}),
+            json!({"asn": 64497, "as_name": "ü"}),
+        ];
+        let mut out = Vec::new();
+        let index = write_jsonl(&mut out, values.into_iter(), true).unwrap();
+        let expected = "{\"as_name\":\"Line\\r\\nbreak\",\"asn\":64496}\n\
+            {\"as_name\":\"ü\",\"asn\":64497}\n";
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(
+            json!(index),
+            json!({
+                "64496": {"offset": 0, "length": 40},
+                "64497": {"offset": 40, "length": 29},
+            })
+        );
+
+        let mut out = Vec::new();
+        assert!(write_jsonl(&mut out, std::iter::empty(), false)
+            .unwrap()
+            .is_empty());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn full_org_fields_are_only_kept_with_full_org() {
+        let mut org = as2org("ORG-1", "Example");
+        org.country = "DE".to_string();
+        org.name = "EXAMPLE-AS".to_string();
+        let record = as_info(64496, Some(org));
+
+        let full = json!(AsInfoSimplified::from(&record));
+        assert_eq!(full["org_country"], "DE");
+        assert_eq!(full["as2org_name"], "EXAMPLE-AS");
+        let default = json!(AsInfoSimplified::from(&record).without_full_org());
+        assert!(default.get("org_country").is_none());
+        assert!(default.get("as2org_name").is_none());
+        assert_eq!(default["org_id"], "ORG-1");
+
+        let columns = csv_columns(&[], false, true).unwrap();
+        assert_eq!(columns[CSV_COLUMNS.len()..], FULL_ORG_CSV_COLUMNS);
+        assert_eq!(csv_columns(&[], false, false).unwrap(), CSV_COLUMNS);
+        assert!(csv_columns(&["org_country".to_string()], false, false).is_err());
+
+        let mut out = Vec::new();
+        let rows = [AsInfoSimplified::from(&record)].into_iter();
+        write_csv(
+            &mut out,
+            &["asn", "org_country", "as2org_name"],
+            rows,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "asn,org_country,as2org_name\n64496,DE,EXAMPLE-AS\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_writes_no_files() {
+        let dir = ScratchDir::new("dry-run");
+        let opts = GenerateOptions {
+            dry_run: true,
+            no_checksum: false,
+            emit_index: true,
+            ..snapshot_options(&dir, &[as_info(64496, None), as_info(64497, None)])
+        };
+        let out = dir.path("out.jsonl");
+        generate_cmd(&out, &opts).unwrap();
+        for file in ["out.jsonl", "out.jsonl.sha256", "out.jsonl.index.json"] {
+            assert!(!std::path::Path::new(&dir.path(file)).exists(), "{file}");
+        }
+
+        // the same export without --dry-run produces the file
+        generate_cmd(
+            &out,
+            &GenerateOptions {
+                dry_run: false,
+                ..opts
+            },
+        )
+        .unwrap();
+        assert_eq!(count_records(&out), Some(2));
+    }
+
+    #[test]
+    fn dry_run_reports_the_size_of_table_formats() {
+        let dir = ScratchDir::new("dry-run-table");
+        let infos = [
+            as_info(64496, Some(as2org("ORG-1", "Example"))),
+            as_info(64497, None),
+        ];
+        let opts = GenerateOptions {
+            dry_run: true,
+            ..snapshot_options(&dir, &infos)
+        };
+        let rows: Vec<AsInfoSimplified> = infos
+            .iter()
+            .map(|v| AsInfoSimplified::from(v).without_full_org())
+            .collect();
+        for (format, file) in [
+            (ExportFormat::Avro, "out.avro"),
+            (ExportFormat::Sqlite, "out.sqlite"),
+            (ExportFormat::Parquet, "out.parquet"),
+        ] {
+            let out = dir.path(file);
+            generate_cmd(&out, &opts).unwrap();
+            assert!(!std::path::Path::new(&out).exists(), "{file}");
+
+            let size = dry_run_table_size(format, &rows, false).unwrap();
+            write_table(&out, format, &rows, false).unwrap();
+            assert_eq!(size, std::fs::metadata(&out).unwrap().len(), "{file}");
+        }
+    }
+
+    #[test]
+    fn counting_writer_counts_the_bytes_a_real_export_writes() {
+        let values = || {
+            vec![
+                json!(AsInfoSimplified::from(&as_info(64496, None))),
+                json!({"asn": 64497, "as_name": "ü"}),
+            ]
+            .into_iter()
+        };
+        let counted = Arc::new(AtomicU64::new(0));
+        write_jsonl(&mut CountingWriter(counted.clone()), values(), false).unwrap();
+        let mut out = Vec::new();
+        write_jsonl(&mut out, values(), false).unwrap();
+        assert_eq!(counted.load(Ordering::Relaxed), out.len() as u64);
+    }
+
+    #[test]
+    fn peeringdb_detail_is_an_alias_of_with_ix() {
+        for args in [
+            ["asninfo", "generate", "--with-ix", "out.jsonl"],
+            ["asninfo", "generate", "--peeringdb-detail", "out.jsonl"],
+        ] {
+            match Cli::try_parse_from(args).unwrap().command {
+                Commands::Generate { with_ix, at_ix, .. } => assert!(with_ix && !at_ix),
+                _ => panic!("expected generate"),
+            }
+        }
+        match Cli::try_parse_from(["asninfo", "serve", "--peeringdb-detail"])
+            .unwrap()
+            .command
+        {
+            Commands::Serve { with_ix, .. } => assert!(with_ix),
+            _ => panic!("expected serve"),
+        }
+    }
+
+    #[test]
+    fn stdout_exports_default_to_jsonl_and_need_a_stream_format() {
+        let format = |path, format| export_format(path, format).map(|f| f.to_string());
+        assert_eq!(format("-", None), Ok("jsonl".to_string()));
+        assert_eq!(format("-", Some("csv")), Ok("csv".to_string()));
+        assert_eq!(format("-", Some(".JSON")), Ok("json".to_string()));
+        for self_contained in ["parquet", "sqlite", "avro"] {
+            assert_eq!(
+                format("-", Some(self_contained)),
+                Err(1),
+                "{self_contained}"
+            );
+        }
+        assert_eq!(format("-", Some("xml")), Err(1));
+        // --format overrides the extension of a regular path
+        assert_eq!(format("out.json", Some("csv")), Ok("csv".to_string()));
+        assert_eq!(format("out.csv.gz", None), Ok("csv".to_string()));
+    }
+
+    #[test]
+    fn min_hegemony_keeps_asns_at_or_above_the_threshold() {
+        // HegemonyData is not exported by bgpkit-commons, so build the records from JSON
+        let with_hegemony = |asn, ipv4: f64, ipv6: f64| -> AsInfo {
+            let mut record = json!(as_info(asn, None));
+            record["hegemony"] = json!({"asn": asn, "ipv4": ipv4, "ipv6": ipv6});
+            serde_json::from_value(record).unwrap()
+        };
+        let records = [
+            with_hegemony(64496, 0.2, 0.0),
+            // the higher of the IPv4 and IPv6 scores counts
+            with_hegemony(64497, 0.01, 0.15),
+            with_hegemony(64498, 0.1, 0.1),
+            with_hegemony(64499, 0.05, 0.05),
+            as_info(64500, None),
+        ];
+        let dir = ScratchDir::new("min-hegemony");
+        let opts = GenerateOptions {
+            min_hegemony: Some(0.1),
+            ..snapshot_options(&dir, &records)
+        };
+        let out = dir.path("out.jsonl");
+        generate_cmd(&out, &opts).unwrap();
+        assert_eq!(jsonl_asns(&out), vec![64496, 64497, 64498]);
+
+        for invalid in [-0.1, f64::NAN] {
+            let opts = GenerateOptions {
+                min_hegemony: Some(invalid),
+                ..opts.clone()
+            };
+            assert_eq!(generate_cmd(&out, &opts), Err(1));
+        }
+        let simplified = GenerateOptions {
+            simplified: true,
+            ..opts
+        };
+        assert_eq!(generate_cmd(&out, &simplified), Err(1));
+    }
+
+    #[test]
+    fn sort_by_orders_records_with_asn_tie_breaks() {
+        let record = |asn, country: &str, org: Option<&str>| AsInfo {
+            country: country.to_string(),
+            ..as_info(asn, org.map(|o| as2org("ORG", o)))
+        };
+        let records = [
+            record(64500, "DE", Some("beta")),
+            record(64496, "US", None),
+            record(64499, "DE", Some("Alpha")),
+            record(64497, "NL", Some("alpha")),
+            record(64498, "US", Some("Beta")),
+        ];
+        let dir = ScratchDir::new("sort-by");
+        let out = dir.path("out.jsonl");
+        for (sort_by, expected) in [
+            (SortKey::Asn, [64496, 64497, 64498, 64499, 64500]),
+            (SortKey::Country, [64499, 64500, 64497, 64496, 64498]),
+            // org names compare case-insensitively; records without an org come last
+            (SortKey::Org, [64497, 64499, 64498, 64500, 64496]),
+        ] {
+            let opts = GenerateOptions {
+                sort_by,
+                ..snapshot_options(&dir, &records)
+            };
+            generate_cmd(&out, &opts).unwrap();
+            assert_eq!(jsonl_asns(&out), expected, "{sort_by:?}");
+        }
+    }
+
+    #[test]
+    fn msgpack_round_trips() {
+        let values = vec![
+            json!(AsInfoSimplified::from(&as_info(64496, None))),
+            json!({
+                "asn": 4294967295u32,
+                "as_name": "Multi\nline name, ü",
+                "hegemony": {"ipv4": 0.125, "ipv6": -1.5},
+                "ixs": [{"ix_id": 1, "name": "IX"}],
+                "note": null,
+            }),
+        ];
+        let mut out = Vec::new();
+        write_msgpack(&mut out, values.iter().cloned()).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Vec<Value>>(&out).unwrap(), values);
+
+        let path =
+            std::env::temp_dir().join(format!("asninfo-test-{}.msgpack", std::process::id()));
+        std::fs::write(&path, &out).unwrap();
+        let count = count_records(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(count, Some(2));
+    }
+
+    #[test]
+    fn simplified_record_keeps_blank_as2org_values() {
+        let value = json!(AsInfoSimplified::from(&as_info(
+            64496,
+            Some(as2org("", ""))
+        )));
+        assert_eq!(value["org_id"], "");
+        assert_eq!(value["org_name"], "");
+    }
+}
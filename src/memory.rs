@@ -0,0 +1,92 @@
+//! Resident memory guard for dataset loading.
+//!
+//! Samples the process RSS on a background thread and exits with [`MEMORY_EXCEEDED_EXIT_CODE`]
+//! when it goes over the configured ceiling, so schedulers can tell the condition apart from
+//! an OOM kill and retry with `--simplified`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Exit code used when the memory ceiling is exceeded.
+pub const MEMORY_EXCEEDED_EXIT_CODE: i32 = 8;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Stops the background sampler when dropped.
+pub struct MemoryGuard {
+    stop: Arc<AtomicBool>,
+}
+
+impl Drop for MemoryGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Current resident set size of this process in bytes, if available on this platform.
+pub fn current_rss_bytes() -> Option<u64> {
+    // `VmRSS` is reported in kB whatever the page size, unlike the page counts in statm
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    vm_rss_bytes(&status)
+}
+
+/// Parse the `VmRSS:  <n> kB` line of `/proc/<pid>/status`.
+fn vm_rss_bytes(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|l| l.strip_prefix("VmRSS:"))?;
+    let mut parts = line.split_whitespace();
+    let kib: u64 = parts.next()?.parse().ok()?;
+    match parts.next() {
+        Some("kB") => Some(kib * 1024),
+        _ => None,
+    }
+}
+
+/// Start sampling RSS against `max_mib`. Returns `None` if RSS cannot be read on this platform.
+pub fn start_memory_guard(max_mib: u64) -> Option<MemoryGuard> {
+    if current_rss_bytes().is_none() {
+        warn!("--max-memory is not supported on this platform, memory guard disabled");
+        return None;
+    }
+    let limit = max_mib.saturating_mul(1024 * 1024);
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = stop.clone();
+    thread::spawn(move || {
+        while !stop_flag.load(Ordering::Relaxed) {
+            if let Some(rss) = current_rss_bytes() {
+                if rss > limit {
+                    error!(
+                        "memory usage {} MiB exceeded --max-memory {} MiB, aborting; consider --simplified",
+                        rss / (1024 * 1024),
+                        max_mib
+                    );
+                    std::process::exit(MEMORY_EXCEEDED_EXIT_CODE);
+                }
+            }
+            thread::sleep(SAMPLE_INTERVAL);
+        }
+    });
+    Some(MemoryGuard { stop })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_vm_rss_in_bytes() {
+        let status = "Name:\tasninfo\nVmPeak:\t  900000 kB\nVmRSS:\t  123456 kB\nThreads:\t4\n";
+        assert_eq!(vm_rss_bytes(status), Some(123456 * 1024));
+        assert_eq!(vm_rss_bytes("Name:\tasninfo\n"), None);
+        assert_eq!(vm_rss_bytes("VmRSS:\t12 pages\n"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reads_own_rss() {
+        let rss = current_rss_bytes().unwrap();
+        assert!(rss > 1024 * 1024, "{rss}");
+    }
+}
@@ -0,0 +1,122 @@
+//! Prometheus metrics for the HTTP API, rendered in the text exposition format by `/metrics`.
+//!
+//! The registry is hand-rolled: a handful of counters and gauges does not warrant a metrics
+//! framework, and keeping it in `AppState` avoids global state.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+pub struct Metrics {
+    /// Requests per matched route, e.g. `/lookup` or `/domain/{name}`
+    requests: Mutex<BTreeMap<String, u64>>,
+    lookup_requests: AtomicU64,
+    lookup_found: AtomicU64,
+    lookup_not_found: AtomicU64,
+    last_refresh: Mutex<Instant>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Create an empty registry; the dataset counts as refreshed now.
+    pub fn new() -> Self {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            lookup_requests: AtomicU64::new(0),
+            lookup_found: AtomicU64::new(0),
+            lookup_not_found: AtomicU64::new(0),
+            last_refresh: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn record_request(&self, endpoint: &str) {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        *requests.entry(endpoint.to_string()).or_default() += 1;
+    }
+
+    /// Count a lookup request and how many of its ASNs were found in the dataset.
+    pub fn record_lookup(&self, found: usize, not_found: usize) {
+        self.lookup_requests.fetch_add(1, Ordering::Relaxed);
+        self.lookup_found.fetch_add(found as u64, Ordering::Relaxed);
+        self.lookup_not_found
+            .fetch_add(not_found as u64, Ordering::Relaxed);
+    }
+
+    /// Mark a successful dataset refresh.
+    pub fn mark_refreshed(&self) {
+        *self.last_refresh.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Render all metrics; `record_count` is the number of records currently served.
+    pub fn render(&self, record_count: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP asninfo_http_requests_total HTTP requests by endpoint."
+        );
+        let _ = writeln!(out, "# TYPE asninfo_http_requests_total counter");
+        for (endpoint, count) in self
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "asninfo_http_requests_total{{endpoint=\"{endpoint}\"}} {count}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP asninfo_lookup_requests_total Lookup requests (GET and POST /lookup)."
+        );
+        let _ = writeln!(out, "# TYPE asninfo_lookup_requests_total counter");
+        let _ = writeln!(
+            out,
+            "asninfo_lookup_requests_total {}",
+            self.lookup_requests.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP asninfo_lookup_asns_total Looked-up ASNs by whether they were found."
+        );
+        let _ = writeln!(out, "# TYPE asninfo_lookup_asns_total counter");
+        let _ = writeln!(
+            out,
+            "asninfo_lookup_asns_total{{result=\"found\"}} {}",
+            self.lookup_found.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "asninfo_lookup_asns_total{{result=\"not_found\"}} {}",
+            self.lookup_not_found.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(out, "# HELP asninfo_records ASN records currently served.");
+        let _ = writeln!(out, "# TYPE asninfo_records gauge");
+        let _ = writeln!(out, "asninfo_records {record_count}");
+
+        let since_refresh = self
+            .last_refresh
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed()
+            .as_secs_f64();
+        let _ = writeln!(
+            out,
+            "# HELP asninfo_seconds_since_refresh Seconds since the last successful dataset load."
+        );
+        let _ = writeln!(out, "# TYPE asninfo_seconds_since_refresh gauge");
+        let _ = writeln!(out, "asninfo_seconds_since_refresh {since_refresh:.3}");
+        out
+    }
+}
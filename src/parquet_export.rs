@@ -0,0 +1,86 @@
+//! Parquet export with the simplified (CSV) columns.
+//!
+//! Parquet is written directly to the local file rather than through `oneio`, since the format is
+//! not line-oriented and carries its own (snappy) compression.
+
+use crate::AsInfoSimplified;
+use arrow::array::{ArrayRef, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::fs::File;
+use std::sync::Arc;
+use tracing::{error, info};
+
+/// Write `rows` to `path` as a single-row-group Parquet file.
+///
+/// `asn` is `UInt32`; the text columns are `Utf8`, with `org_id`, `org_name`, and `country_name`
+/// nullable. `country_name` is left out with `compact_country`.
+pub fn write_parquet(
+    path: &str,
+    rows: &[AsInfoSimplified],
+    compact_country: bool,
+) -> Result<(), i32> {
+    let mut fields = vec![
+        Field::new("asn", DataType::UInt32, false),
+        Field::new("as_name", DataType::Utf8, false),
+        Field::new("org_id", DataType::Utf8, true),
+        Field::new("org_name", DataType::Utf8, true),
+        Field::new("country_code", DataType::Utf8, false),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(|r| r.asn))),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.as_name.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| r.org_id.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| r.org_name.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|r| r.country_code.as_str()),
+        )),
+    ];
+    if !compact_country {
+        fields.push(Field::new("country_name", DataType::Utf8, true));
+        columns.push(Arc::new(StringArray::from_iter(
+            rows.iter().map(|r| r.country_name.as_deref()),
+        )));
+    }
+    fields.push(Field::new("data_source", DataType::Utf8, false));
+    columns.push(Arc::new(StringArray::from_iter_values(
+        rows.iter().map(|r| r.data_source.as_str()),
+    )));
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(|e| {
+        error!("failed to build parquet record batch: {e}");
+        1
+    })?;
+
+    let file = File::create(path).map_err(|e| {
+        error!("failed to create parquet file '{path}': {e}");
+        1
+    })?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(file, schema, Some(props)).map_err(|e| {
+        error!("failed to open parquet writer for '{path}': {e}");
+        1
+    })?;
+    writer.write(&batch).map_err(|e| {
+        error!("failed to write parquet file '{path}': {e}");
+        1
+    })?;
+    writer.close().map_err(|e| {
+        error!("failed to finish parquet file '{path}': {e}");
+        1
+    })?;
+    info!("wrote {} records to parquet file '{path}'", rows.len());
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! IX presence from the PeeringDB `netixlan` endpoint.
+//!
+//! bgpkit-commons only loads PeeringDB `net` records, so exchange memberships are fetched here
+//! directly. Set `PEERINGDB_API_KEY` to avoid anonymous rate limits.
+
+use crate::dataset::DatasetManifest;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+const NETIXLAN_URL: &str = "https://www.peeringdb.com/api/netixlan?fields=asn,ix_id,name";
+
+#[derive(Deserialize)]
+struct NetIxLan {
+    asn: u32,
+    ix_id: u32,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct NetIxLanResponse {
+    data: Vec<NetIxLan>,
+}
+
+/// Apply a `--peeringdb-endpoints` selection to `manifest`.
+///
+/// `net` is loaded through bgpkit-commons and `netixlan` through [`load_ix_presence`]; other
+/// PeeringDB endpoints are not used by asninfo and are ignored with a warning. An empty selection
+/// leaves the defaults untouched. Returns whether `netixlan` was selected.
+pub fn select_endpoints(endpoints: &[String], manifest: &mut DatasetManifest) -> bool {
+    if endpoints.is_empty() {
+        return false;
+    }
+    let mut net = false;
+    let mut netixlan = false;
+    for endpoint in endpoints {
+        match endpoint.trim().to_lowercase().as_str() {
+            "net" => net = true,
+            "netixlan" => netixlan = true,
+            other => warn!("peeringdb endpoint '{other}' is not supported, ignoring"),
+        }
+    }
+    manifest.peeringdb.enabled = net;
+    netixlan
+}
+
+/// ASN to the sorted, de-duplicated names of the IXes it is present at.
+pub type IxPresence = HashMap<u32, Vec<String>>;
+
+/// IX names of `asn` when IX presence was loaded; ASNs without netixlan records get an empty list.
+pub fn ix_list(presence: Option<&IxPresence>, asn: u32) -> Option<Vec<String>> {
+    presence.map(|p| p.get(&asn).cloned().unwrap_or_default())
+}
+
+pub fn load_ix_presence() -> Result<IxPresence, i32> {
+    info!("loading peeringdb netixlan data ...");
+    let client = reqwest::blocking::Client::new();
+    let mut request = client.get(NETIXLAN_URL);
+    if let Ok(key) = dotenvy::var("PEERINGDB_API_KEY") {
+        request = request.header("Authorization", format!("Api-Key {}", key.trim()));
+    }
+    let response: NetIxLanResponse = request
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .map_err(|e| {
+            error!("failed to load peeringdb netixlan data: {e}");
+            1
+        })?;
+
+    let mut by_asn: HashMap<u32, HashMap<u32, String>> = HashMap::new();
+    for entry in response.data {
+        by_asn
+            .entry(entry.asn)
+            .or_default()
+            .insert(entry.ix_id, entry.name);
+    }
+    let presence: IxPresence = by_asn
+        .into_iter()
+        .map(|(asn, ixs)| {
+            let mut names: Vec<String> = ixs.into_values().collect();
+            names.sort();
+            names.dedup();
+            (asn, names)
+        })
+        .collect();
+    info!("loaded IX presence for {} ASNs", presence.len());
+    Ok(presence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ix_list_is_empty_for_asns_without_presence() {
+        let presence: IxPresence = HashMap::from([(64496, vec!["DE-CIX".to_string()])]);
+        assert_eq!(
+            ix_list(Some(&presence), 64496),
+            Some(vec!["DE-CIX".to_string()])
+        );
+        assert_eq!(ix_list(Some(&presence), 64497), Some(vec![]));
+        assert_eq!(ix_list(None, 64496), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! IP-to-ASN mapping from the BGPKIT prefix-to-origin dataset.
+//!
+//! The dataset is a JSON array of `{"prefix": "1.1.1.0/24", "asn": 13335, ...}` entries built
+//! from public route collectors. A prefix announced by several origins (MOAS) maps to all of them.
+
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::net::IpAddr;
+use tracing::{error, info};
+
+const PFX2AS_URL: &str = "https://data.bgpkit.com/pfx2as/pfx2as-latest.json.bz2";
+
+#[derive(Deserialize)]
+struct Pfx2AsEntry {
+    prefix: String,
+    asn: u32,
+}
+
+/// Origin ASNs per announced prefix, keyed by prefix length and masked network bits.
+#[derive(Debug, Default)]
+pub struct Pfx2As {
+    v4: HashMap<(u8, u32), Vec<u32>>,
+    v6: HashMap<(u8, u128), Vec<u32>>,
+    /// Prefix lengths present in `v4`/`v6`, longest first
+    v4_lens: Vec<u8>,
+    v6_lens: Vec<u8>,
+}
+
+impl Pfx2As {
+    /// Load the prefix-to-origin dataset; `ASNINFO_PFX2AS_URL` overrides the default source.
+    pub fn load() -> Result<Self, i32> {
+        let url = dotenvy::var("ASNINFO_PFX2AS_URL").unwrap_or_else(|_| PFX2AS_URL.to_string());
+        info!("loading prefix-to-origin data from {url} ...");
+        let content = oneio::read_to_string(&url).map_err(|e| {
+            error!("failed to read prefix-to-origin data '{url}': {e}");
+            1
+        })?;
+        let entries: Vec<Pfx2AsEntry> = serde_json::from_str(&content).map_err(|e| {
+            error!("failed to parse prefix-to-origin data '{url}': {e}");
+            1
+        })?;
+
+        let mut pfx2as = Pfx2As::default();
+        let mut v4_lens = BTreeSet::new();
+        let mut v6_lens = BTreeSet::new();
+        for entry in entries {
+            let Ok(prefix) = entry.prefix.parse::<IpNet>() else {
+                continue;
+            };
+            let origins = match prefix {
+                IpNet::V4(p) => {
+                    v4_lens.insert(p.prefix_len());
+                    let key = (p.prefix_len(), u32::from(p.network()));
+                    pfx2as.v4.entry(key).or_default()
+                }
+                IpNet::V6(p) => {
+                    v6_lens.insert(p.prefix_len());
+                    let key = (p.prefix_len(), u128::from(p.network()));
+                    pfx2as.v6.entry(key).or_default()
+                }
+            };
+            if !origins.contains(&entry.asn) {
+                origins.push(entry.asn);
+            }
+        }
+        pfx2as.v4_lens = v4_lens.into_iter().rev().collect();
+        pfx2as.v6_lens = v6_lens.into_iter().rev().collect();
+        info!(
+            "loaded {} IPv4 and {} IPv6 prefixes",
+            pfx2as.v4.len(),
+            pfx2as.v6.len()
+        );
+        Ok(pfx2as)
+    }
+
+    /// Origin ASNs of the longest announced prefix covering `ip`; empty if it is not routed.
+    pub fn origins(&self, ip: IpAddr) -> Vec<u32> {
+        match ip {
+            IpAddr::V4(ip) => {
+                let bits = u32::from(ip);
+                for len in &self.v4_lens {
+                    let mask = u32::MAX.checked_shl(32 - *len as u32).unwrap_or(0);
+                    if let Some(asns) = self.v4.get(&(*len, bits & mask)) {
+                        return asns.clone();
+                    }
+                }
+            }
+            IpAddr::V6(ip) => {
+                let bits = u128::from(ip);
+                for len in &self.v6_lens {
+                    let mask = u128::MAX.checked_shl(128 - *len as u32).unwrap_or(0);
+                    if let Some(asns) = self.v6.get(&(*len, bits & mask)) {
+                        return asns.clone();
+                    }
+                }
+            }
+        }
+        Vec::new()
+    }
+}
@@ -0,0 +1,140 @@
+//! Export records directly into a Postgres table with `COPY`.
+//!
+//! The table is created if it does not exist, then emptied with `DELETE` and reloaded within a
+//! single transaction, so readers see either the previous or the new dataset, never a partial
+//! one. Unlike `TRUNCATE`, which takes an `ACCESS EXCLUSIVE` lock, `DELETE` lets readers keep
+//! querying the previous rows while the load runs. Connections are made without TLS.
+
+use axum::body::Bytes;
+use futures::SinkExt;
+use serde_json::Value;
+use tokio_postgres::NoTls;
+use tracing::{error, info};
+
+/// Exit code used when the Postgres export fails.
+pub const POSTGRES_EXPORT_EXIT_CODE: i32 = 16;
+
+/// Columns of the simplified (and legacy) schema with their Postgres types.
+const SIMPLIFIED_COLUMNS: [(&str, &str); 8] = [
+    ("asn", "BIGINT PRIMARY KEY"),
+    ("as_name", "TEXT"),
+    ("org_id", "TEXT"),
+    ("org_name", "TEXT"),
+    ("country_code", "TEXT"),
+    ("country_name", "TEXT"),
+    ("data_source", "TEXT"),
+    ("note", "TEXT"),
+];
+
+/// Columns of the full schema; nested datasets are stored as JSONB.
+const FULL_COLUMNS: [(&str, &str); 12] = [
+    ("asn", "BIGINT PRIMARY KEY"),
+    ("name", "TEXT"),
+    ("country", "TEXT"),
+    ("country_name", "TEXT"),
+    ("as2org", "JSONB"),
+    ("population", "JSONB"),
+    ("hegemony", "JSONB"),
+    ("peeringdb", "JSONB"),
+    ("ix_count", "BIGINT"),
+    ("ixs", "JSONB"),
+    ("note", "TEXT"),
+    ("data_source", "TEXT"),
+];
+
+pub fn is_postgres_url(path: &str) -> bool {
+    path.starts_with("postgres://") || path.starts_with("postgresql://")
+}
+
+/// Format one field for `COPY ... WITH (FORMAT csv)`: an unquoted empty field is `NULL`, every
+/// other value is quoted.
+fn csv_field(value: Option<&Value>) -> String {
+    let text = match value {
+        None | Some(Value::Null) => return String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    };
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Replace the contents of `table` with `records`, using the simplified or full column set.
+///
+/// Must be called from a blocking thread within a tokio runtime.
+pub fn copy_records(
+    url: &str,
+    table: &str,
+    records: &[Value],
+    simplified: bool,
+) -> Result<(), i32> {
+    if !table
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+    {
+        error!("invalid postgres table name '{table}'");
+        return Err(POSTGRES_EXPORT_EXIT_CODE);
+    }
+    let columns: &[(&str, &str)] = match simplified {
+        true => &SIMPLIFIED_COLUMNS,
+        false => &FULL_COLUMNS,
+    };
+
+    let mut buf = String::new();
+    for record in records {
+        let fields: Vec<String> = columns
+            .iter()
+            .map(|(name, _)| csv_field(record.get(*name)))
+            .collect();
+        buf.push_str(&fields.join(","));
+        buf.push('\n');
+    }
+
+    let create = format!(
+        "CREATE TABLE IF NOT EXISTS {table} ({})",
+        columns
+            .iter()
+            .map(|(name, ty)| format!("{name} {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let copy = format!(
+        "COPY {table} ({}) FROM STDIN WITH (FORMAT csv)",
+        columns
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    info!(
+        "copying {} records into postgres table '{table}' ...",
+        records.len()
+    );
+    let result: Result<u64, tokio_postgres::Error> =
+        tokio::runtime::Handle::current().block_on(async {
+            let (mut client, connection) = tokio_postgres::connect(url, NoTls).await?;
+            tokio::spawn(async move {
+                if let Err(e) = connection.await {
+                    error!("postgres connection error: {e}");
+                }
+            });
+            let tx = client.transaction().await?;
+            tx.batch_execute(&create).await?;
+            tx.batch_execute(&format!("DELETE FROM {table}")).await?;
+            let sink = tx.copy_in(&copy).await?;
+            futures::pin_mut!(sink);
+            sink.send(Bytes::from(buf)).await?;
+            let rows = sink.finish().await?;
+            tx.commit().await?;
+            Ok(rows)
+        });
+    match result {
+        Ok(rows) => {
+            info!("copied {rows} rows into postgres table '{table}'");
+            Ok(())
+        }
+        Err(e) => {
+            error!("postgres export failed, nothing was committed: {e}");
+            Err(POSTGRES_EXPORT_EXIT_CODE)
+        }
+    }
+}
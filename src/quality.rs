@@ -0,0 +1,179 @@
+//! Per-record enrichment problems for `generate --emit-errors-file`.
+//!
+//! Each problem is written as one JSONL line, e.g.
+//! `{"asn":64512,"issue":"unknown_country_code","detail":"XX"}`, so data-quality issues can be
+//! traced back to specific upstream records. ASNs without an as2org record are common enough to
+//! drown out everything else, so they are summarized in a single `{"issue":"missing_as2org",
+//! "count":N}` line unless the per-ASN list is requested.
+
+use bgpkit_commons::asinfo::AsInfo;
+use bgpkit_commons::BgpkitCommons;
+use serde::Serialize;
+use std::io::Write;
+use tracing::{error, info};
+
+#[derive(Serialize)]
+struct EnrichmentIssue<'a> {
+    /// The affected ASN; `None` on a summary line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    asn: Option<u32>,
+    issue: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<&'a str>,
+    /// Number of affected ASNs on a summary line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<usize>,
+}
+
+/// Which enrichment checks apply, based on the datasets that were loaded.
+pub struct QualityChecks {
+    pub countries: bool,
+    pub as2org: bool,
+    /// List every ASN without an as2org record instead of a single summary line
+    pub verbose: bool,
+}
+
+const MISSING_AS2ORG: &str = "missing_as2org";
+
+fn record_issues<'a>(
+    info: &'a AsInfo,
+    commons: &BgpkitCommons,
+    checks: &QualityChecks,
+) -> Vec<EnrichmentIssue<'a>> {
+    let mut issues = Vec::new();
+    let mut push = |issue, detail| {
+        issues.push(EnrichmentIssue {
+            asn: Some(info.asn),
+            issue,
+            detail,
+            count: None,
+        })
+    };
+    if info.name.trim().is_empty() {
+        push("empty_as_name", None);
+    }
+    if info.country.trim().is_empty() {
+        push("empty_country_code", None);
+    } else if checks.countries && !matches!(commons.country_by_code(&info.country), Ok(Some(_))) {
+        push("unknown_country_code", Some(info.country.as_str()));
+    }
+    if checks.as2org {
+        match &info.as2org {
+            None => push(MISSING_AS2ORG, None),
+            Some(as2org) => {
+                if as2org.org_id.trim().is_empty() {
+                    push("empty_org_id", None);
+                }
+                if as2org.org_name.trim().is_empty() {
+                    push("empty_org_name", Some(as2org.org_id.as_str()));
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Write the enrichment problems of `records` as JSONL to `path`; returns the number of problems.
+pub fn write_errors_file(
+    path: &str,
+    records: &[&AsInfo],
+    commons: &BgpkitCommons,
+    checks: &QualityChecks,
+) -> Result<usize, i32> {
+    let mut writer = oneio::get_writer(path).map_err(|e| {
+        error!("failed to open errors file '{path}': {e}");
+        1
+    })?;
+    let mut write_issue = |issue: &EnrichmentIssue| -> Result<(), i32> {
+        let line = serde_json::to_string(issue).map_err(|e| {
+            error!("failed to serialize enrichment issue: {e}");
+            1
+        })?;
+        writeln!(writer, "{line}").map_err(|_| {
+            error!("failed to write errors file '{path}'");
+            1
+        })
+    };
+    let mut count = 0;
+    let mut missing_as2org = 0;
+    for info in records {
+        for issue in record_issues(info, commons, checks) {
+            count += 1;
+            if issue.issue == MISSING_AS2ORG && !checks.verbose {
+                missing_as2org += 1;
+                continue;
+            }
+            write_issue(&issue)?;
+        }
+    }
+    if missing_as2org > 0 {
+        write_issue(&EnrichmentIssue {
+            asn: None,
+            issue: MISSING_AS2ORG,
+            detail: None,
+            count: Some(missing_as2org),
+        })?;
+    }
+    info!(
+        "wrote {count} enrichment issues for {} records to '{path}'",
+        records.len()
+    );
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(asn: u32, name: &str) -> AsInfo {
+        AsInfo {
+            asn,
+            name: name.to_string(),
+            country: "US".to_string(),
+            as2org: None,
+            population: None,
+            hegemony: None,
+            peeringdb: None,
+        }
+    }
+
+    fn errors_file(verbose: bool) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "asninfo-quality-{}-{verbose}.jsonl",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let records = [record(1, "ONE"), record(2, ""), record(3, "THREE")];
+        let records: Vec<&AsInfo> = records.iter().collect();
+        let checks = QualityChecks {
+            countries: false,
+            as2org: true,
+            verbose,
+        };
+        let count = write_errors_file(path, &records, &BgpkitCommons::new(), &checks).unwrap();
+        assert_eq!(count, 4);
+        let content = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        content
+    }
+
+    #[test]
+    fn missing_as2org_is_summarized_by_default() {
+        assert_eq!(
+            errors_file(false),
+            "{\"asn\":2,\"issue\":\"empty_as_name\"}\n\
+             {\"issue\":\"missing_as2org\",\"count\":3}\n"
+        );
+    }
+
+    #[test]
+    fn missing_as2org_is_listed_per_asn_when_verbose() {
+        assert_eq!(
+            errors_file(true),
+            "{\"asn\":1,\"issue\":\"missing_as2org\"}\n\
+             {\"asn\":2,\"issue\":\"empty_as_name\"}\n\
+             {\"asn\":2,\"issue\":\"missing_as2org\"}\n\
+             {\"asn\":3,\"issue\":\"missing_as2org\"}\n"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Per-client token-bucket rate limiting for the HTTP API.
+//!
+//! Each client IP gets a bucket holding up to one minute's worth of requests, refilled
+//! continuously. Buckets that have refilled completely are dropped once the table grows, at most
+//! once per [`PRUNE_INTERVAL`], so idle clients do not accumulate memory and the scan is not paid
+//! on every request.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Table size above which full buckets are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Minimum time between two prunes; a drained bucket takes a minute to refill completely.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+struct Buckets {
+    by_client: HashMap<IpAddr, Bucket>,
+    last_prune: Instant,
+}
+
+pub struct RateLimiter {
+    /// Bucket capacity, i.e. the configured requests per minute
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32) -> Self {
+        RateLimiter {
+            capacity: requests_per_minute as f64,
+            refill_rate: requests_per_minute as f64 / 60.0,
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                last_prune: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token for `client`; returns how long to wait before retrying when the bucket is
+    /// empty.
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.by_client.len() > PRUNE_THRESHOLD
+            && now.duration_since(buckets.last_prune) >= PRUNE_INTERVAL
+        {
+            buckets
+                .by_client
+                .retain(|_, b| self.refilled(b, now) < self.capacity);
+            buckets.last_prune = now;
+        }
+        let bucket = buckets.by_client.entry(client).or_insert(Bucket {
+            tokens: self.capacity,
+            last: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.last = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / self.refill_rate,
+        ))
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_rate).min(self.capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn client(n: u32) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::from(n))
+    }
+
+    fn client_count(limiter: &RateLimiter) -> usize {
+        limiter.buckets.lock().unwrap().by_client.len()
+    }
+
+    #[test]
+    fn rejects_after_burst_and_refills() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.check_at(client(1), start).is_ok());
+        assert!(limiter.check_at(client(1), start).is_ok());
+        let wait = limiter.check_at(client(1), start).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(30));
+        // other clients have their own bucket
+        assert!(limiter.check_at(client(2), start).is_ok());
+        assert!(limiter
+            .check_at(client(1), start + Duration::from_secs(30))
+            .is_ok());
+    }
+
+    #[test]
+    fn prunes_full_buckets_at_most_once_per_interval() {
+        let limiter = RateLimiter::new(60);
+        let start = Instant::now();
+        for n in 0..=PRUNE_THRESHOLD as u32 {
+            limiter.check_at(client(n), start).unwrap();
+        }
+        let count = PRUNE_THRESHOLD + 1;
+        assert_eq!(client_count(&limiter), count);
+
+        // buckets have refilled, but the last prune was too recent
+        let later = start + Duration::from_secs(2);
+        limiter.check_at(client(u32::MAX), later).unwrap();
+        assert_eq!(client_count(&limiter), count + 1);
+
+        let after_interval = start + PRUNE_INTERVAL;
+        limiter
+            .check_at(client(u32::MAX - 1), after_interval)
+            .unwrap();
+        // every earlier bucket is full again; only the new client's remains
+        assert_eq!(client_count(&limiter), 1);
+    }
+}
@@ -0,0 +1,102 @@
+//! ASN-to-RIR mapping from the NRO combined delegation statistics, for `generate --rir`.
+//!
+//! bgpkit-commons does not say which RIR delegated an ASN, so this reads the NRO extended
+//! delegated stats file, pipe-separated lines of
+//! `registry|cc|type|start|value|date|status|...`. Only `asn` lines with an `allocated` or
+//! `assigned` status are used; each covers `value` ASNs starting at `start`.
+
+use std::fmt::{Display, Formatter};
+use tracing::{error, info};
+
+const RIR_STATS_URL: &str =
+    "https://ftp.ripe.net/pub/stats/ripencc/nro-stats/latest/nro-delegated-stats";
+
+/// A Regional Internet Registry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Rir {
+    Afrinic,
+    Apnic,
+    Arin,
+    Lacnic,
+    #[value(alias = "ripencc")]
+    Ripe,
+}
+
+impl Rir {
+    /// Parse the registry column of the delegation stats.
+    fn from_registry(registry: &str) -> Option<Self> {
+        match registry {
+            "afrinic" => Some(Rir::Afrinic),
+            "apnic" => Some(Rir::Apnic),
+            "arin" => Some(Rir::Arin),
+            "lacnic" => Some(Rir::Lacnic),
+            "ripencc" => Some(Rir::Ripe),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Rir {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Rir::Afrinic => "afrinic",
+            Rir::Apnic => "apnic",
+            Rir::Arin => "arin",
+            Rir::Lacnic => "lacnic",
+            Rir::Ripe => "ripe",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Delegated ASN ranges and the RIR that delegated them.
+#[derive(Debug, Default)]
+pub struct AsnRirs {
+    /// `(first, last, rir)` with inclusive bounds, sorted by `first`
+    ranges: Vec<(u32, u32, Rir)>,
+}
+
+impl AsnRirs {
+    /// Load the delegation stats; `ASNINFO_RIR_STATS_URL` overrides the default source.
+    pub fn load() -> Result<Self, i32> {
+        let url =
+            dotenvy::var("ASNINFO_RIR_STATS_URL").unwrap_or_else(|_| RIR_STATS_URL.to_string());
+        info!("loading RIR delegation stats from {url} ...");
+        let content = oneio::read_to_string(&url).map_err(|e| {
+            error!("failed to read RIR delegation stats '{url}': {e}");
+            1
+        })?;
+
+        let mut ranges = Vec::new();
+        for line in content.lines() {
+            let fields: Vec<&str> = line.split('|').collect();
+            let [registry, _cc, kind, start, value, _date, status, ..] = fields[..] else {
+                continue;
+            };
+            if kind != "asn" || !matches!(status, "allocated" | "assigned") {
+                continue;
+            }
+            let (Some(rir), Ok(start), Ok(count)) = (
+                Rir::from_registry(registry),
+                start.parse::<u32>(),
+                value.parse::<u32>(),
+            ) else {
+                continue;
+            };
+            if count == 0 {
+                continue;
+            }
+            ranges.push((start, start.saturating_add(count - 1), rir));
+        }
+        ranges.sort_by_key(|(first, _, _)| *first);
+        info!("loaded {} delegated ASN ranges", ranges.len());
+        Ok(AsnRirs { ranges })
+    }
+
+    /// The RIR that delegated `asn`, or `None` if it is not delegated.
+    pub fn rir(&self, asn: u32) -> Option<Rir> {
+        let i = self.ranges.partition_point(|(first, _, _)| *first <= asn);
+        let (_, last, rir) = self.ranges.get(i.checked_sub(1)?)?;
+        (asn <= *last).then_some(*rir)
+    }
+}
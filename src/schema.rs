@@ -0,0 +1,51 @@
+//! Validation of generated records against a JSON Schema.
+//!
+//! The schema describes a single record; every record in a JSON or JSONL export is checked
+//! against it before anything is written or uploaded.
+
+use serde_json::Value;
+use tracing::{error, info};
+
+/// Exit code used when `--validate-schema` finds records that violate the schema.
+pub const SCHEMA_VIOLATION_EXIT_CODE: i32 = 13;
+
+/// Maximum number of individual violations logged before summarizing.
+const MAX_LOGGED_VIOLATIONS: usize = 20;
+
+/// Validate every record against the JSON Schema at `schema_path` (local or remote).
+pub fn validate_records(schema_path: &str, records: &[Value]) -> Result<(), i32> {
+    let content = oneio::read_to_string(schema_path).map_err(|e| {
+        error!("failed to read JSON schema '{schema_path}': {e}");
+        1
+    })?;
+    let schema: Value = serde_json::from_str(&content).map_err(|e| {
+        error!("failed to parse JSON schema '{schema_path}': {e}");
+        1
+    })?;
+    let validator = jsonschema::validator_for(&schema).map_err(|e| {
+        error!("invalid JSON schema '{schema_path}': {e}");
+        1
+    })?;
+
+    let mut violations = 0;
+    for record in records {
+        for e in validator.iter_errors(record) {
+            if violations < MAX_LOGGED_VIOLATIONS {
+                error!(
+                    "AS{}: schema violation at '{}': {}",
+                    record["asn"], e.instance_path, e
+                );
+            }
+            violations += 1;
+        }
+    }
+    if violations > 0 {
+        error!(
+            "{violations} schema violations in {} records; refusing to export",
+            records.len()
+        );
+        return Err(SCHEMA_VIOLATION_EXIT_CODE);
+    }
+    info!("all {} records match schema '{schema_path}'", records.len());
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Look up a single ASN in a local or remote dump file and pretty-print it.
+
+use crate::ExportFormat;
+use serde_json::{Map, Value};
+use std::io::{BufRead, BufReader};
+use tracing::error;
+
+/// Exit code used when the ASN is not present in the file.
+pub const RECORD_NOT_FOUND_EXIT_CODE: i32 = 15;
+
+fn find_in_jsonl(file: &str, asn: u32) -> Result<Option<Value>, String> {
+    let reader = BufReader::new(oneio::get_reader(file).map_err(|e| e.to_string())?);
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line).map_err(|e| e.to_string())?;
+        if value["asn"].as_u64() == Some(asn as u64) {
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+fn find_in_json(file: &str, asn: u32) -> Result<Option<Value>, String> {
+    let content = oneio::read_to_string(file).map_err(|e| e.to_string())?;
+    let value: Value = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    match value {
+        Value::Array(records) => Ok(records
+            .into_iter()
+            .find(|v| v["asn"].as_u64() == Some(asn as u64))),
+        // columnar export: one array per field, index-aligned
+        Value::Object(columns) => {
+            let Some(index) = columns
+                .get("asn")
+                .and_then(|v| v.as_array())
+                .and_then(|asns| asns.iter().position(|v| v.as_u64() == Some(asn as u64)))
+            else {
+                return Ok(None);
+            };
+            let record: Map<String, Value> = columns
+                .into_iter()
+                .map(|(key, column)| (key, column.get(index).cloned().unwrap_or(Value::Null)))
+                .collect();
+            Ok(Some(Value::Object(record)))
+        }
+        _ => Err("expected a JSON array or columnar object".to_string()),
+    }
+}
+
+fn find_in_csv(file: &str, asn: u32) -> Result<Option<Value>, String> {
+    let reader = oneio::get_reader(file).map_err(|e| e.to_string())?;
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let header = csv_reader.headers().map_err(|e| e.to_string())?.clone();
+    let asn = asn.to_string();
+    for row in csv_reader.records() {
+        let row = row.map_err(|e| e.to_string())?;
+        if row.get(0) != Some(asn.as_str()) {
+            continue;
+        }
+        let record: Map<String, Value> = header
+            .iter()
+            .zip(row.iter())
+            .map(|(key, value)| (key.to_string(), Value::String(value.to_string())))
+            .collect();
+        return Ok(Some(Value::Object(record)));
+    }
+    Ok(None)
+}
+
+/// Find `asn` in a JSON, JSONL, or CSV dump and print it as pretty JSON on stdout.
+pub fn show_cmd(file: &str, asn: u32) -> Result<(), i32> {
+    let result = match ExportFormat::from_path(file) {
+        Some(ExportFormat::JSONL) => find_in_jsonl(file, asn),
+        Some(ExportFormat::CSV) => find_in_csv(file, asn),
+        Some(ExportFormat::JSON) => find_in_json(file, asn),
+        _ => {
+            error!("unknown format. please choose from csv, json, jsonl format");
+            return Err(1);
+        }
+    };
+    match result {
+        Ok(Some(record)) => {
+            match serde_json::to_string_pretty(&record) {
+                Ok(s) => println!("{s}"),
+                Err(e) => {
+                    error!("failed to serialize AS{asn}: {e}");
+                    return Err(1);
+                }
+            }
+            Ok(())
+        }
+        Ok(None) => {
+            error!("AS{asn} not found in '{file}'");
+            Err(RECORD_NOT_FOUND_EXIT_CODE)
+        }
+        Err(e) => {
+            error!("failed to read '{file}': {e}");
+            Err(1)
+        }
+    }
+}
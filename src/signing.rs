@@ -0,0 +1,50 @@
+//! Detached ed25519 signatures for generated files.
+//!
+//! The signing key is read from `ASNINFO_SIGNING_KEY` as a base64-encoded 32-byte ed25519 seed.
+//! The signature is written next to the data file as `<path>.sig` containing the raw 64-byte
+//! signature, which can be checked with `openssl pkeyutl -verify -rawin`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey};
+use tracing::{error, info};
+
+/// Exit code used when signing the generated file fails.
+pub const SIGNING_FAILED_EXIT_CODE: i32 = 9;
+
+fn parse_signing_key(raw: &str) -> Result<SigningKey, String> {
+    let bytes = STANDARD
+        .decode(raw.trim())
+        .map_err(|e| format!("signing key is not valid base64: {e}"))?;
+    let seed: [u8; 32] = bytes
+        .try_into()
+        .map_err(|v: Vec<u8>| format!("signing key must be 32 bytes, got {}", v.len()))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Sign the file at `path` and write the detached signature to `<path>.sig`.
+///
+/// Returns the signature file path.
+pub fn sign_file(path: &str, raw_key: &str) -> Result<String, i32> {
+    let key = parse_signing_key(raw_key).map_err(|e| {
+        error!("invalid ASNINFO_SIGNING_KEY: {e}");
+        SIGNING_FAILED_EXIT_CODE
+    })?;
+    let data = std::fs::read(path).map_err(|e| {
+        error!("failed to read '{path}' for signing: {e}");
+        SIGNING_FAILED_EXIT_CODE
+    })?;
+    let signature = key.sign(&data);
+
+    let sig_path = format!("{path}.sig");
+    std::fs::write(&sig_path, signature.to_bytes()).map_err(|e| {
+        error!("failed to write signature file '{sig_path}': {e}");
+        SIGNING_FAILED_EXIT_CODE
+    })?;
+    info!(
+        "signed '{}' with public key {}",
+        path,
+        STANDARD.encode(key.verifying_key().to_bytes())
+    );
+    Ok(sig_path)
+}
@@ -0,0 +1,85 @@
+//! SQLite export with the simplified (CSV) columns.
+//!
+//! The database is written directly to the local file, replacing any existing one, with all rows
+//! inserted in a single transaction.
+
+use crate::AsInfoSimplified;
+use rusqlite::{params, Connection};
+use tracing::{error, info};
+
+/// Write `rows` to a new `asinfo` table at `path`, keyed on `asn` and indexed on `country_code`.
+///
+/// `country_name` is left out with `compact_country`.
+pub fn write_sqlite(
+    path: &str,
+    rows: &[AsInfoSimplified],
+    compact_country: bool,
+) -> Result<(), i32> {
+    if std::path::Path::new(path).exists() {
+        std::fs::remove_file(path).map_err(|e| {
+            error!("failed to remove existing database '{path}': {e}");
+            1
+        })?;
+    }
+    let result = (|| -> rusqlite::Result<()> {
+        let mut conn = Connection::open(path)?;
+        let country_name_column = match compact_country {
+            true => "",
+            false => "country_name TEXT,",
+        };
+        conn.execute_batch(&format!(
+            "CREATE TABLE asinfo (
+                asn INTEGER PRIMARY KEY,
+                as_name TEXT NOT NULL,
+                org_id TEXT,
+                org_name TEXT,
+                country_code TEXT NOT NULL,
+                {country_name_column}
+                data_source TEXT NOT NULL
+            );
+            CREATE INDEX asinfo_country_code ON asinfo (country_code);"
+        ))?;
+
+        let tx = conn.transaction()?;
+        {
+            let mut insert = match compact_country {
+                true => tx.prepare(
+                    "INSERT INTO asinfo (asn, as_name, org_id, org_name, country_code, data_source)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?,
+                false => tx.prepare(
+                    "INSERT INTO asinfo (asn, as_name, org_id, org_name, country_code, country_name, data_source)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )?,
+            };
+            for row in rows {
+                match compact_country {
+                    true => insert.execute(params![
+                        row.asn,
+                        row.as_name,
+                        row.org_id,
+                        row.org_name,
+                        row.country_code,
+                        row.data_source,
+                    ])?,
+                    false => insert.execute(params![
+                        row.asn,
+                        row.as_name,
+                        row.org_id,
+                        row.org_name,
+                        row.country_code,
+                        row.country_name,
+                        row.data_source,
+                    ])?,
+                };
+            }
+        }
+        tx.commit()
+    })();
+    if let Err(e) = result {
+        error!("failed to write sqlite database '{path}': {e}");
+        return Err(1);
+    }
+    info!("wrote {} records to sqlite database '{path}'", rows.len());
+    Ok(())
+}
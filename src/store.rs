@@ -0,0 +1,117 @@
+//! In-memory record storage for the HTTP API.
+//!
+//! Records are kept either as plain structs or, with `--compress-records`, as individually
+//! zstd-compressed JSON that is decompressed on each access. On a 120,000-record dump the
+//! compressed form lowers RSS after startup from 261 to 156 MiB, but every scan (`matching`, and so
+//! /search and /by-country) decompresses all records, about 1.1 s instead of 35 ms. Refreshes build
+//! the new records uncompressed, so peak RSS during a refresh is unchanged; see the README for the
+//! full measurements.
+//!
+//! The records are shared behind an `Arc`, so cloning a `RecordMap` is a cheap snapshot that a
+//! streaming response can read from while a refresh swaps in new data.
+
+use crate::api::AsInfoOut;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::error;
+
+/// zstd level used for stored records; low levels already capture the repeated keys and names.
+const RECORD_COMPRESSION_LEVEL: i32 = 3;
+
+#[derive(Clone)]
+pub enum RecordMap {
+    Plain(Arc<HashMap<u32, AsInfoOut>>),
+    Compressed(Arc<HashMap<u32, Box<[u8]>>>),
+}
+
+fn compress(info: &AsInfoOut) -> Option<Box<[u8]>> {
+    let bytes = serde_json::to_vec(info).ok()?;
+    match zstd::bulk::compress(&bytes, RECORD_COMPRESSION_LEVEL) {
+        Ok(compressed) => Some(compressed.into_boxed_slice()),
+        Err(e) => {
+            error!("failed to compress AS{}: {e}", info.inner.asn);
+            None
+        }
+    }
+}
+
+fn decompress(asn: u32, bytes: &[u8]) -> Option<AsInfoOut> {
+    let decoded = zstd::stream::decode_all(bytes)
+        .map_err(|e| error!("failed to decompress AS{asn}: {e}"))
+        .ok()?;
+    serde_json::from_slice(&decoded)
+        .map_err(|e| error!("failed to decode AS{asn}: {e}"))
+        .ok()
+}
+
+impl RecordMap {
+    pub fn new(map: HashMap<u32, AsInfoOut>, compressed: bool) -> Self {
+        if !compressed {
+            return RecordMap::Plain(Arc::new(map));
+        }
+        RecordMap::Compressed(Arc::new(
+            map.iter()
+                .filter_map(|(asn, info)| Some((*asn, compress(info)?)))
+                .collect(),
+        ))
+    }
+
+    pub fn record_count(&self) -> usize {
+        match self {
+            RecordMap::Plain(map) => map.len(),
+            RecordMap::Compressed(map) => map.len(),
+        }
+    }
+
+    /// All stored ASNs, in no particular order.
+    pub fn asns(&self) -> Vec<u32> {
+        match self {
+            RecordMap::Plain(map) => map.keys().copied().collect(),
+            RecordMap::Compressed(map) => map.keys().copied().collect(),
+        }
+    }
+
+    pub fn contains(&self, asn: &u32) -> bool {
+        match self {
+            RecordMap::Plain(map) => map.contains_key(asn),
+            RecordMap::Compressed(map) => map.contains_key(asn),
+        }
+    }
+
+    /// An owned copy of the record for `asn`.
+    pub fn get(&self, asn: &u32) -> Option<AsInfoOut> {
+        match self {
+            RecordMap::Plain(map) => map.get(asn).cloned(),
+            RecordMap::Compressed(map) => decompress(*asn, map.get(asn)?),
+        }
+    }
+
+    /// Owned copies of all records matching `predicate`, in no particular order.
+    pub fn matching(&self, predicate: impl Fn(&AsInfoOut) -> bool) -> Vec<AsInfoOut> {
+        match self {
+            RecordMap::Plain(map) => map.values().filter(|v| predicate(v)).cloned().collect(),
+            RecordMap::Compressed(map) => map
+                .iter()
+                .filter_map(|(asn, bytes)| decompress(*asn, bytes))
+                .filter(|v| predicate(v))
+                .collect(),
+        }
+    }
+
+    /// Modify all records in place; compressed records are decompressed for the duration of `f`
+    /// and compressed again afterwards. Snapshots taken with `clone` keep the old records.
+    pub fn update<T>(&mut self, f: impl FnOnce(&mut HashMap<u32, AsInfoOut>) -> T) -> T {
+        match self {
+            RecordMap::Plain(map) => f(Arc::make_mut(map)),
+            RecordMap::Compressed(stored) => {
+                let mut map: HashMap<u32, AsInfoOut> = stored
+                    .iter()
+                    .filter_map(|(asn, bytes)| Some((*asn, decompress(*asn, bytes)?)))
+                    .collect();
+                let result = f(&mut map);
+                *self = RecordMap::new(map, true);
+                result
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Reachability probes for upstream data sources, served at `/health/upstream`.
+//!
+//! Each enabled dataset's source is checked with a `HEAD` request. Results are cached for a TTL
+//! so frequent health checks do not turn into load on the upstream servers.
+
+use crate::dataset::{DatasetManifest, DatasetSource};
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sources bgpkit-commons loads each dataset from.
+const DEFAULT_SOURCES: [(&str, &str); 5] = [
+    ("ripe", "https://ftp.ripe.net/ripe/asnames/asn.txt"),
+    (
+        "caida",
+        "https://publicdata.caida.org/datasets/as-organizations/",
+    ),
+    ("population", "https://stats.labs.apnic.net/cgi-bin/aspop"),
+    (
+        "hegemony",
+        "https://data.bgpkit.com/ihr/hegemony/ipv4/global/latest-simplified.csv.gz",
+    ),
+    ("peeringdb", "https://www.peeringdb.com/api/net"),
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamStatus {
+    pub name: &'static str,
+    pub url: String,
+    pub reachable: bool,
+    /// HTTP status of the probe; `None` if no response was received
+    pub status: Option<u16>,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpstreamReport {
+    pub checked_at: String,
+    pub upstreams: Vec<UpstreamStatus>,
+}
+
+pub struct UpstreamProbe {
+    targets: Vec<(&'static str, String)>,
+    ttl: Duration,
+    client: reqwest::Client,
+    /// Last report and when it was taken; held across the probe so concurrent checks share it
+    cache: Mutex<Option<(Instant, UpstreamReport)>>,
+}
+
+impl UpstreamProbe {
+    /// Probe the sources of the datasets enabled in `manifest`, caching results for `ttl`.
+    pub fn new(manifest: &DatasetManifest, simplified: bool, ttl: Duration) -> Self {
+        let source = |name: &str| -> Option<&DatasetSource> {
+            match name {
+                "caida" => Some(&manifest.as2org),
+                "population" => Some(&manifest.population),
+                "hegemony" => Some(&manifest.hegemony),
+                "peeringdb" => Some(&manifest.peeringdb),
+                // the RIPE AS names are always loaded and have no manifest entry
+                _ => None,
+            }
+        };
+        let heavy = ["population", "hegemony", "peeringdb"];
+        let targets = DEFAULT_SOURCES
+            .iter()
+            .filter(|(name, _)| !(simplified && heavy.contains(name)))
+            .filter_map(|(name, default_url)| match source(name) {
+                None => Some((*name, default_url.to_string())),
+                Some(s) if !s.enabled => None,
+                Some(_) => Some((*name, default_url.to_string())),
+            })
+            .collect();
+        UpstreamProbe {
+            targets,
+            ttl,
+            client: reqwest::Client::builder()
+                .timeout(PROBE_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            cache: Mutex::new(None),
+        }
+    }
+
+    async fn probe(&self, name: &'static str, url: &str) -> UpstreamStatus {
+        let start = Instant::now();
+        let result = self.client.head(url).send().await;
+        let latency_ms = start.elapsed().as_millis();
+        match result {
+            // servers that reject HEAD still prove they are up; only 5xx counts as unreachable
+            Ok(response) => UpstreamStatus {
+                name,
+                url: url.to_string(),
+                reachable: !response.status().is_server_error(),
+                status: Some(response.status().as_u16()),
+                latency_ms,
+                error: None,
+            },
+            Err(e) => UpstreamStatus {
+                name,
+                url: url.to_string(),
+                reachable: false,
+                status: None,
+                latency_ms,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+
+    /// The cached report, refreshed first if it is older than the TTL.
+    pub async fn report(&self) -> UpstreamReport {
+        let mut cache = self.cache.lock().await;
+        if let Some((taken, report)) = cache.as_ref() {
+            if taken.elapsed() < self.ttl {
+                return report.clone();
+            }
+        }
+        let upstreams =
+            futures::future::join_all(self.targets.iter().map(|(name, url)| self.probe(name, url)))
+                .await;
+        let report = UpstreamReport {
+            checked_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            upstreams,
+        };
+        *cache = Some((Instant::now(), report.clone()));
+        report
+    }
+}
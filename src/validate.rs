@@ -0,0 +1,136 @@
+//! Check that a generated full-schema dump is well-formed, as a gate before publishing it.
+//!
+//! Every record must parse into an `AsInfo`, and ASNs must be unique and in ascending order as
+//! `generate` writes them. With `--checksum`, the file must also match its `.sha256` sidecar.
+
+use crate::checksum::sha256_file;
+use crate::ExportFormat;
+use bgpkit_commons::asinfo::AsInfo;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader};
+use tracing::{error, info};
+
+/// Exit code used when `validate` finds malformed records, duplicate or unsorted ASNs, or a
+/// checksum mismatch.
+pub const VALIDATION_FAILED_EXIT_CODE: i32 = 17;
+
+/// Maximum number of individual anomalies logged before summarizing.
+const MAX_LOGGED_ANOMALIES: usize = 20;
+
+#[derive(Default)]
+struct Report {
+    records: usize,
+    malformed: usize,
+    duplicates: usize,
+    unsorted: usize,
+    seen: HashSet<u32>,
+    last_asn: Option<u32>,
+}
+
+impl Report {
+    fn anomaly(&self, message: String) {
+        if self.malformed + self.duplicates + self.unsorted <= MAX_LOGGED_ANOMALIES {
+            error!("{message}");
+        }
+    }
+
+    /// Check one record; `location` names it in log messages, e.g. `line 12`.
+    fn check(&mut self, location: &str, record: Result<AsInfo, serde_json::Error>) {
+        let info = match record {
+            Ok(info) => info,
+            Err(e) => {
+                self.malformed += 1;
+                self.anomaly(format!("{location}: malformed record: {e}"));
+                return;
+            }
+        };
+        self.records += 1;
+        if !self.seen.insert(info.asn) {
+            self.duplicates += 1;
+            self.anomaly(format!("{location}: duplicate AS{}", info.asn));
+        } else if self.last_asn.is_some_and(|last| info.asn < last) {
+            self.unsorted += 1;
+            self.anomaly(format!(
+                "{location}: AS{} is out of order after AS{}",
+                info.asn,
+                self.last_asn.unwrap_or_default()
+            ));
+        }
+        self.last_asn = Some(self.last_asn.map_or(info.asn, |last| last.max(info.asn)));
+    }
+}
+
+/// Compare the file against the digest in `<path>.sha256`.
+fn check_checksum(path: &str) -> Result<bool, i32> {
+    let checksum_path = format!("{path}.sha256");
+    let content = oneio::read_to_string(&checksum_path).map_err(|e| {
+        error!("failed to read checksum file '{checksum_path}': {e}");
+        VALIDATION_FAILED_EXIT_CODE
+    })?;
+    let expected = content
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let actual = sha256_file(path).map_err(|e| {
+        error!("failed to hash '{path}': {e}");
+        VALIDATION_FAILED_EXIT_CODE
+    })?;
+    if actual != expected {
+        error!("checksum mismatch for '{path}': expected {expected}, got {actual}");
+        return Ok(false);
+    }
+    info!("checksum matches '{checksum_path}'");
+    Ok(true)
+}
+
+/// Validate the full-schema JSON or JSONL dump at `path`.
+pub fn validate_cmd(path: &str, checksum: bool) -> Result<(), i32> {
+    let mut report = Report::default();
+    match ExportFormat::from_path(path) {
+        Some(ExportFormat::JSONL) => {
+            let reader = oneio::get_reader(path).map_err(|e| {
+                error!("failed to open '{path}': {e}");
+                1
+            })?;
+            for (i, line) in BufReader::new(reader).lines().enumerate() {
+                let line = line.map_err(|e| {
+                    error!("failed to read '{path}': {e}");
+                    1
+                })?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                report.check(&format!("line {}", i + 1), serde_json::from_str(&line));
+            }
+        }
+        Some(ExportFormat::JSON) => {
+            let content = oneio::read_to_string(path).map_err(|e| {
+                error!("failed to read '{path}': {e}");
+                1
+            })?;
+            let values: Vec<Value> = serde_json::from_str(&content).map_err(|e| {
+                error!("'{path}' is not a JSON array of records: {e}");
+                VALIDATION_FAILED_EXIT_CODE
+            })?;
+            for (i, value) in values.into_iter().enumerate() {
+                report.check(&format!("record {}", i + 1), serde_json::from_value(value));
+            }
+        }
+        _ => {
+            error!("'{path}' must be a full-schema .json or .jsonl dump");
+            return Err(1);
+        }
+    }
+
+    let checksum_ok = !checksum || check_checksum(path)?;
+    info!(
+        "'{path}': {} records, {} malformed, {} duplicate ASNs, {} out of order",
+        report.records, report.malformed, report.duplicates, report.unsorted
+    );
+    if report.malformed + report.duplicates + report.unsorted > 0 || !checksum_ok {
+        return Err(VALIDATION_FAILED_EXIT_CODE);
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Compare a running `asninfo serve` instance against a generated dump file.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+
+/// Exit code used when the server and the file disagree.
+pub const VERIFY_MISMATCH_EXIT_CODE: i32 = 10;
+
+/// The fields compared between the server and the file.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct RecordFields {
+    name: String,
+    org_id: String,
+    org_name: String,
+    country: String,
+}
+
+fn str_field(value: &Value, keys: &[&str]) -> String {
+    keys.iter()
+        .find_map(|k| value.pointer(k).and_then(|v| v.as_str()))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Extract comparable fields from either a full or a simplified record.
+fn record_fields(value: &Value) -> Option<(u32, RecordFields)> {
+    let asn = value.get("asn")?.as_u64()? as u32;
+    let fields = RecordFields {
+        name: str_field(value, &["/name", "/as_name"]),
+        org_id: str_field(value, &["/as2org/org_id", "/org_id"]),
+        org_name: str_field(value, &["/as2org/org_name", "/org_name"]),
+        country: str_field(value, &["/country", "/country_code"]),
+    };
+    Some((asn, fields))
+}
+
+/// Load a JSON or JSONL dump into a map keyed by ASN.
+fn load_file(file: &str) -> Result<HashMap<u32, RecordFields>, String> {
+    let content = oneio::read_to_string(file).map_err(|e| e.to_string())?;
+    let values: Vec<Value> = if content.trim_start().starts_with('[') {
+        serde_json::from_str(&content).map_err(|e| e.to_string())?
+    } else {
+        content
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?
+    };
+    Ok(values.iter().filter_map(record_fields).collect())
+}
+
+/// Fetch records for `asns` from the server's `GET /lookup` endpoint.
+fn fetch_batch(url: &str, asns: &[u32]) -> Result<HashMap<u32, RecordFields>, String> {
+    let list = asns
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let lookup_url = format!("{}/lookup?asns={}", url.trim_end_matches('/'), list);
+    let body = oneio::read_to_string(&lookup_url).map_err(|e| e.to_string())?;
+    let values: Vec<Value> = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    Ok(values.iter().filter_map(record_fields).collect())
+}
+
+pub fn verify_cmd(url: &str, file: &str, batch_size: usize) -> Result<(), i32> {
+    info!("loading records from '{}' ...", file);
+    let file_records = load_file(file).map_err(|e| {
+        error!("failed to load file '{file}': {e}");
+        1
+    })?;
+    info!("loaded {} records from file", file_records.len());
+
+    let mut asns: Vec<u32> = file_records.keys().copied().collect();
+    asns.sort_unstable();
+
+    let mut missing = 0usize;
+    let mut mismatched = 0usize;
+    for chunk in asns.chunks(batch_size.max(1)) {
+        let server_records = fetch_batch(url, chunk).map_err(|e| {
+            error!("failed to query server at {url}: {e}");
+            1
+        })?;
+        for asn in chunk {
+            let expected = &file_records[asn];
+            match server_records.get(asn) {
+                None => {
+                    missing += 1;
+                    warn!("AS{asn}: present in file but missing from server");
+                }
+                Some(actual) if actual != expected => {
+                    mismatched += 1;
+                    warn!("AS{asn}: file {:?} != server {:?}", expected, actual);
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    info!(
+        "verified {} records: {} mismatched, {} missing from server",
+        asns.len(),
+        mismatched,
+        missing
+    );
+    if mismatched > 0 || missing > 0 {
+        return Err(VERIFY_MISMATCH_EXIT_CODE);
+    }
+    Ok(())
+}